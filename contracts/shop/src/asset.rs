@@ -0,0 +1,158 @@
+use cosmwasm_std::{
+    to_binary, Addr, BankMsg, Coin, CosmosMsg, StdResult, Storage, Uint128, WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
+use cw_storage_plus::Map;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ContractError;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    Token { contract_addr: Addr },
+    NativeToken { denom: String },
+}
+
+impl AssetInfo {
+    // cw20 addresses and native denoms don't overlap, so either one identifies the asset
+    pub fn key(&self) -> String {
+        match self {
+            AssetInfo::Token { contract_addr } => contract_addr.to_string(),
+            AssetInfo::NativeToken { denom } => denom.clone(),
+        }
+    }
+}
+
+// an amount paid in the coffee token or in native coins
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
+
+impl Asset {
+    pub fn token(contract_addr: Addr, amount: Uint128) -> Self {
+        Asset {
+            info: AssetInfo::Token { contract_addr },
+            amount,
+        }
+    }
+
+    pub fn native(coin: Coin) -> Self {
+        Asset {
+            info: AssetInfo::NativeToken { denom: coin.denom },
+            amount: coin.amount,
+        }
+    }
+
+    pub fn transfer_msg(&self, recipient: &Addr) -> StdResult<CosmosMsg> {
+        Ok(match &self.info {
+            AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: self.amount,
+                })?,
+                funds: vec![],
+            }),
+            AssetInfo::NativeToken { denom } => CosmosMsg::Bank(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![Coin {
+                    denom: denom.clone(),
+                    amount: self.amount,
+                }],
+            }),
+        })
+    }
+}
+
+// payments of open orders held by the contract, by asset key
+pub const ESCROW: Map<String, Uint128> = Map::new("escrow");
+
+pub fn escrowed(storage: &dyn Storage, info: &AssetInfo) -> StdResult<Uint128> {
+    Ok(ESCROW.may_load(storage, info.key())?.unwrap_or_default())
+}
+
+pub fn add_escrow(storage: &mut dyn Storage, asset: &Asset) -> StdResult<()> {
+    ESCROW.update(storage, asset.info.key(), |held| -> StdResult<_> {
+        Ok(held.unwrap_or_default().checked_add(asset.amount)?)
+    })?;
+    Ok(())
+}
+
+pub fn release_escrow(storage: &mut dyn Storage, asset: &Asset) -> StdResult<()> {
+    ESCROW.update(storage, asset.info.key(), |held| -> StdResult<_> {
+        Ok(held.unwrap_or_default().checked_sub(asset.amount)?)
+    })?;
+    Ok(())
+}
+
+// revenue of every shop that wasn't withdrawn yet, by shop key and asset key
+pub const LEDGER: Map<(String, String), Asset> = Map::new("ledger");
+// the shop ledgers added up, by asset key
+pub const LEDGER_TOTALS: Map<String, Uint128> = Map::new("ledger_totals");
+
+pub fn ledger_balance(
+    storage: &dyn Storage,
+    shop_key: &str,
+    info: &AssetInfo,
+) -> StdResult<Uint128> {
+    Ok(LEDGER
+        .may_load(storage, (shop_key.to_string(), info.key()))?
+        .map(|asset| asset.amount)
+        .unwrap_or_default())
+}
+
+pub fn ledger_total(storage: &dyn Storage, info: &AssetInfo) -> StdResult<Uint128> {
+    Ok(LEDGER_TOTALS
+        .may_load(storage, info.key())?
+        .unwrap_or_default())
+}
+
+pub fn credit_ledger(storage: &mut dyn Storage, shop_key: &str, asset: &Asset) -> StdResult<()> {
+    LEDGER.update(
+        storage,
+        (shop_key.to_string(), asset.info.key()),
+        |entry| -> StdResult<_> {
+            let amount = entry.map(|entry| entry.amount).unwrap_or_default();
+            Ok(Asset {
+                info: asset.info.clone(),
+                amount: amount.checked_add(asset.amount)?,
+            })
+        },
+    )?;
+    LEDGER_TOTALS.update(storage, asset.info.key(), |total| -> StdResult<_> {
+        Ok(total.unwrap_or_default().checked_add(asset.amount)?)
+    })?;
+    Ok(())
+}
+
+// a shop can't pay out more than its ledger holds
+pub fn debit_ledger(
+    storage: &mut dyn Storage,
+    shop_key: &str,
+    asset: &Asset,
+) -> Result<(), ContractError> {
+    let balance = ledger_balance(storage, shop_key, &asset.info)?;
+    let rest = balance
+        .checked_sub(asset.amount)
+        .map_err(|_| ContractError::NotEnoughFunds {})?;
+    let key = (shop_key.to_string(), asset.info.key());
+    if rest.is_zero() {
+        LEDGER.remove(storage, key);
+    } else {
+        LEDGER.save(
+            storage,
+            key,
+            &Asset {
+                info: asset.info.clone(),
+                amount: rest,
+            },
+        )?;
+    }
+    let total = ledger_total(storage, &asset.info)?.checked_sub(asset.amount)?;
+    LEDGER_TOTALS.save(storage, asset.info.key(), &total)?;
+    Ok(())
+}
@@ -1,13 +1,180 @@
-use crate::products::{CoffeeCup, CoffeeRecipe, IngredientPortion};
+use crate::asset::{Asset, AssetInfo};
+use crate::error::ContractError;
+use crate::products::{CoffeeCup, CoffeeRecipe, IngredientInfo, IngredientPortion, MenuItem};
+use crate::state::PauseInfo;
+use cosmwasm_std::{
+    Addr, Coin, Empty, Order, OverflowError, StdError, StdResult, Storage, Uint128,
+};
 use cw_storage_plus::Map;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct CoffeeState {
-    pub menu: Vec<CoffeeCup>,
-    pub recipes: Vec<CoffeeRecipe>,
+    // holds every role, staff roles are granted per shop in SHOP_ROLES
+    pub owner: Addr,
+    // inactive shops don't sell coffee
+    pub active: bool,
+    // a paused shop doesn't sell coffee until the pause is lifted or expires
+    #[serde(default)]
+    pub pause: Option<PauseInfo>,
+    // accumulated sales per cw20 token, a replaced coffee token keeps its own entry, and
+    // in native coins
+    pub revenue: Vec<Asset>,
+    pub native_revenue: Vec<Coin>,
+    // items are kept in the display order
+    pub menu: Vec<MenuItem>,
+    // ids are never reused, so integrations can rely on them
+    pub last_item_id: Uint128,
+    // ingredients registered by the shop, each one has a stock entry in ingredient_portions
+    pub ingredients: Vec<IngredientInfo>,
     pub ingredient_portions: Vec<IngredientPortion>,
 }
 
 pub const COFFEE_STATE: Map<String, CoffeeState> = Map::new("coffee_state");
+// shop keys by owner, so the shops of an owner are found without walking every shop
+pub const SHOPS_BY_OWNER: Map<(Addr, String), Empty> = Map::new("shops_by_owner");
+
+// saves a new shop and indexes it under its owner
+pub fn save_new_shop(
+    storage: &mut dyn Storage,
+    key: &str,
+    coffee_state: &CoffeeState,
+) -> StdResult<()> {
+    COFFEE_STATE.save(storage, key.to_string(), coffee_state)?;
+    SHOPS_BY_OWNER.save(
+        storage,
+        (coffee_state.owner.clone(), key.to_string()),
+        &Empty {},
+    )
+}
+
+// hands every shop of `from` over to `to` and returns their keys
+pub fn move_shops(storage: &mut dyn Storage, from: &Addr, to: &Addr) -> StdResult<Vec<String>> {
+    let keys = SHOPS_BY_OWNER
+        .prefix(from.clone())
+        .keys(storage, None, None, Order::Ascending)
+        .map(|key| String::from_utf8(key).map_err(StdError::invalid_utf8))
+        .collect::<StdResult<Vec<_>>>()?;
+    for key in keys.iter() {
+        let mut coffee_state = COFFEE_STATE.load(storage, key.clone())?;
+        coffee_state.owner = to.clone();
+        COFFEE_STATE.save(storage, key.clone(), &coffee_state)?;
+        SHOPS_BY_OWNER.remove(storage, (from.clone(), key.clone()));
+        SHOPS_BY_OWNER.save(storage, (to.clone(), key.clone()), &Empty {})?;
+    }
+    Ok(keys)
+}
+
+pub fn load_shop(storage: &dyn Storage, key: &str) -> Result<CoffeeState, ContractError> {
+    COFFEE_STATE
+        .may_load(storage, key.to_string())?
+        .ok_or_else(|| ContractError::ShopNotFound {
+            key: key.to_string(),
+        })
+}
+
+impl CoffeeState {
+    pub fn new(
+        owner: Addr,
+        menu: Vec<CoffeeCup>,
+        recipes: Vec<CoffeeRecipe>,
+        ingredients: Vec<IngredientInfo>,
+    ) -> Self {
+        let ingredient_portions = ingredients
+            .iter()
+            .map(|info| IngredientPortion {
+                ingredient: info.id.clone(),
+                weight: Uint128::zero(),
+            })
+            .collect();
+        let mut state = CoffeeState {
+            owner,
+            active: true,
+            pause: None,
+            revenue: vec![],
+            native_revenue: vec![],
+            menu: vec![],
+            last_item_id: Uint128::zero(),
+            ingredients,
+            ingredient_portions,
+        };
+        for (cup, recipe) in menu.into_iter().zip(recipes) {
+            state.add_item(cup, recipe);
+        }
+        state
+    }
+
+    pub fn item_index(&self, id: Uint128) -> Option<usize> {
+        self.menu.iter().position(|item| item.id == id)
+    }
+
+    // appends the item to the menu and returns its new id
+    pub fn add_item(&mut self, cup: CoffeeCup, recipe: CoffeeRecipe) -> Uint128 {
+        self.last_item_id += Uint128::new(1);
+        self.menu.push(MenuItem {
+            id: self.last_item_id,
+            cup,
+            recipe,
+            modifiers: vec![],
+        });
+        self.last_item_id
+    }
+
+    // books a picked up order in the revenue of the asset it was paid with
+    pub fn add_revenue(&mut self, payment: &Asset) -> Result<(), OverflowError> {
+        match &payment.info {
+            AssetInfo::Token { .. } => match self
+                .revenue
+                .iter_mut()
+                .find(|asset| asset.info == payment.info)
+            {
+                Some(asset) => asset.amount = asset.amount.checked_add(payment.amount)?,
+                None => self.revenue.push(payment.clone()),
+            },
+            AssetInfo::NativeToken { denom } => {
+                self.add_native_revenue(Coin::new(payment.amount.u128(), denom.clone()))?
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_revenue(&mut self, payment: &Asset) -> StdResult<()> {
+        match &payment.info {
+            AssetInfo::Token { .. } => {
+                let asset = self
+                    .revenue
+                    .iter_mut()
+                    .find(|asset| asset.info == payment.info)
+                    .ok_or_else(|| StdError::not_found("Asset"))?;
+                asset.amount = asset.amount.checked_sub(payment.amount)?;
+            }
+            AssetInfo::NativeToken { denom } => {
+                self.remove_native_revenue(&Coin::new(payment.amount.u128(), denom.clone()))?
+            }
+        }
+        Ok(())
+    }
+
+    fn add_native_revenue(&mut self, amount: Coin) -> Result<(), OverflowError> {
+        match self
+            .native_revenue
+            .iter_mut()
+            .find(|coin| coin.denom == amount.denom)
+        {
+            Some(coin) => coin.amount = coin.amount.checked_add(amount.amount)?,
+            None => self.native_revenue.push(amount),
+        }
+        Ok(())
+    }
+
+    fn remove_native_revenue(&mut self, amount: &Coin) -> StdResult<()> {
+        let coin = self
+            .native_revenue
+            .iter_mut()
+            .find(|coin| coin.denom == amount.denom)
+            .ok_or_else(|| StdError::not_found("Coin"))?;
+        coin.amount = coin.amount.checked_sub(amount.amount)?;
+        Ok(())
+    }
+}
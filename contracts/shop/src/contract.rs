@@ -1,20 +1,45 @@
-use std::ops::{Add, Mul};
-
-use cosmwasm_std::{Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, to_binary, Uint128};
+use cosmwasm_std::{
+    attr, coins, from_binary, to_binary, Addr, Attribute, BankMsg, Binary, BlockInfo, Coin,
+    Decimal, Deps, DepsMut, Env, MessageInfo, Order, Response, StdError, StdResult, Storage,
+    Timestamp, Uint128,
+};
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{Cw20ReceiveMsg, Expiration};
+use cw_storage_plus::{Bound, U128Key};
 
-use crate::coffee_state::{COFFEE_STATE, CoffeeState};
+use crate::asset::{
+    add_escrow, credit_ledger, debit_ledger, escrowed, ledger_balance, ledger_total,
+    release_escrow, Asset, AssetInfo, LEDGER,
+};
+use crate::coffee_state::{load_shop, move_shops, save_new_shop, CoffeeState, COFFEE_STATE};
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use crate::migration::run_migrations;
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ReceiveMsg};
+use crate::order::{
+    next_order_id, order_id_from_key, CoffeeOrder, OrderStatus, CUSTOMER_ORDERS, ORDERS,
+    SHOP_QUEUE,
+};
 use crate::products::{
-    AVERAGE_CUP_WEIGHT, calculate_total_ingredient_weight, CoffeeCup, CoffeeRecipe,
-    Ingredient, IngredientCupShare, IngredientPortion, IngredientsResponse, MenuResponse,
-    OwnerResponse, RecipesResponse, SHARE_PRECISION, check_weight};
-use crate::state::{State, STATE};
-use crate::token::{query_token_balance, execute_transfer, execute_transfer_from};
+    add_ingredient_needs, apply_modifiers, check_stock, check_weight, max_cups, validate_modifiers,
+    validate_recipe, AvailabilityResponse, CoffeeCup, CoffeeRecipe, ConfigResponse, CupSize,
+    IngredientCupShare, IngredientInfo, IngredientPortion, IngredientsResponse, ItemAvailability,
+    LedgerResponse, MenuEntry, MenuItem, MenuResponse, Modifier, OrderLine, OrdersResponse,
+    OwnerResponse, RecipeInfo, RecipesResponse, ReconcileResponse, RolesResponse, ShopInfoResponse,
+    ShopsResponse, SimulatePurchaseResponse, SizeAvailability, StaffRoles, Unit,
+    WithdrawalsResponse, AVERAGE_CUP_WEIGHT, DEFAULT_SIZE, SHARE_PRECISION,
+};
+use crate::roles::{require_role, roles_of, Role, SHOP_ROLES};
+use crate::state::{
+    FeeConfig, OwnershipProposal, PauseInfo, State, DEFAULT_ORDER_TIMEOUT, OWNERSHIP_PROPOSAL,
+    STATE,
+};
+use crate::token::{
+    execute_transfer, execute_transfer_from, query_token_allowance, query_token_balance,
+};
+use crate::withdrawal::{next_withdrawal_id, Withdrawal, WITHDRAWALS};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:shop";
@@ -27,6 +52,12 @@ const CAPPUCCINO: &str = "Cappuccino";
 const LATE: &str = "Late";
 const AMERICANO: &str = "Americano";
 
+// default ingredients
+pub(crate) const BEANS: &str = "beans";
+pub(crate) const WATER: &str = "water";
+pub(crate) const MILK: &str = "milk";
+pub(crate) const SUGAR: &str = "sugar";
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
@@ -35,104 +66,77 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     let state = State {
-        owner: info.sender.clone(),
-        balance: Uint128::zero(),
-        coffee_token_addr: deps.api.addr_validate(&msg.token_addr.to_string())?,
+        owner: Some(info.sender.clone()),
+        coffee_token_addr: deps.api.addr_validate(msg.token_addr.as_ref())?,
+        previous_token_addrs: vec![],
+        order_timeout: DEFAULT_ORDER_TIMEOUT,
+        pause: None,
+        default_cup_weight: Uint128::new(AVERAGE_CUP_WEIGHT),
+        fee: None,
     };
 
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     STATE.save(deps.storage, &state)?;
 
-    let coffee_state = CoffeeState {
-        ingredient_portions: vec![
-            IngredientPortion {
-                ingredient: Ingredient::Beans,
-                weight: Uint128::zero(),
-            },
-            IngredientPortion {
-                ingredient: Ingredient::Water,
-                weight: Uint128::zero(),
-            },
-            IngredientPortion {
-                ingredient: Ingredient::Milk,
-                weight: Uint128::zero(),
-            },
-            IngredientPortion {
-                ingredient: Ingredient::Sugar,
-                weight: Uint128::zero(),
-            },
-        ],
-        menu: vec![
+    let coffee_state = CoffeeState::new(
+        info.sender.clone(),
+        vec![
             CoffeeCup {
                 name: String::from(CAPPUCCINO),
-                price: DEFAULT_PRICE,
+                sizes: default_sizes(),
             },
             CoffeeCup {
                 name: String::from(LATE),
-                price: DEFAULT_PRICE,
+                sizes: default_sizes(),
             },
             CoffeeCup {
                 name: String::from(AMERICANO),
-                price: DEFAULT_PRICE,
+                sizes: default_sizes(),
             },
         ],
-        recipes: vec![
+        vec![
             CoffeeRecipe {
                 ingredients: vec![
                     IngredientCupShare {
-                        ingredient_type: Ingredient::Water,
+                        ingredient_type: String::from(WATER),
                         share: Uint128::new(45),
                     },
                     IngredientCupShare {
-                        ingredient_type: Ingredient::Beans,
+                        ingredient_type: String::from(BEANS),
                         share: Uint128::new(25),
                     },
                     IngredientCupShare {
-                        ingredient_type: Ingredient::Milk,
+                        ingredient_type: String::from(MILK),
                         share: Uint128::new(25),
                     },
                     IngredientCupShare {
-                        ingredient_type: Ingredient::Sugar,
+                        ingredient_type: String::from(SUGAR),
                         share: Uint128::new(5),
                     },
                 ],
             },
+            default_late_recipe(),
             CoffeeRecipe {
                 ingredients: vec![
                     IngredientCupShare {
-                        ingredient_type: Ingredient::Beans,
-                        share: Uint128::new(2),
-                    },
-                    IngredientCupShare {
-                        ingredient_type: Ingredient::Water,
-                        share: Uint128::new(45),
-                    },
-                    IngredientCupShare {
-                        ingredient_type: Ingredient::Beans,
-                        share: Uint128::new(25),
-                    },
-                ],
-            },
-            CoffeeRecipe {
-                ingredients: vec![
-                    IngredientCupShare {
-                        ingredient_type: Ingredient::Water,
+                        ingredient_type: String::from(WATER),
                         share: Uint128::new(70),
                     },
                     IngredientCupShare {
-                        ingredient_type: Ingredient::Beans,
+                        ingredient_type: String::from(BEANS),
                         share: Uint128::new(25),
                     },
                     IngredientCupShare {
-                        ingredient_type: Ingredient::Sugar,
+                        ingredient_type: String::from(SUGAR),
                         share: Uint128::new(5),
                     },
                 ],
             },
         ],
-    };
+        default_ingredient_catalogue(),
+    );
 
-    COFFEE_STATE.save(deps.storage, msg.shop_key, &coffee_state)?;
+    save_new_shop(deps.storage, &msg.shop_key, &coffee_state)?;
 
     Ok(Response::new()
         .add_attribute("method", "instantiate")
@@ -140,21 +144,157 @@ pub fn instantiate(
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let version = get_contract_version(deps.storage)?;
+    if version.contract != CONTRACT_NAME {
+        return Err(ContractError::WrongContract {
+            name: version.contract,
+        });
+    }
+
+    let applied = run_migrations(deps.storage, &version.version, CONTRACT_VERSION)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let mut res = Response::new()
+        .add_attribute("method", "migrate")
+        .add_attribute("from_version", version.version)
+        .add_attribute("to_version", CONTRACT_VERSION);
+    if !applied.is_empty() {
+        res = res.add_attribute("migrations", applied.join(","));
+    }
+    Ok(res)
+}
+
+// a single regular size for the default menu
+fn default_sizes() -> Vec<CupSize> {
+    vec![CupSize {
+        name: String::from(DEFAULT_SIZE),
+        weight: Uint128::new(AVERAGE_CUP_WEIGHT),
+        price: DEFAULT_PRICE,
+        native_price: None,
+    }]
+}
+
+// the Late of the default menu, shops migrated from v0.1.0 get it too
+pub(crate) fn default_late_recipe() -> CoffeeRecipe {
+    CoffeeRecipe {
+        ingredients: vec![
+            IngredientCupShare {
+                ingredient_type: String::from(WATER),
+                share: Uint128::new(25),
+            },
+            IngredientCupShare {
+                ingredient_type: String::from(BEANS),
+                share: Uint128::new(25),
+            },
+            IngredientCupShare {
+                ingredient_type: String::from(MILK),
+                share: Uint128::new(50),
+            },
+        ],
+    }
+}
+
+// every shop starts with these ingredients registered, more can be added later
+pub(crate) fn default_ingredient_catalogue() -> Vec<IngredientInfo> {
+    vec![
+        IngredientInfo {
+            id: String::from(BEANS),
+            name: String::from("Beans"),
+            unit: Unit::Grams,
+        },
+        IngredientInfo {
+            id: String::from(WATER),
+            name: String::from("Water"),
+            unit: Unit::Millilitres,
+        },
+        IngredientInfo {
+            id: String::from(MILK),
+            name: String::from("Milk"),
+            unit: Unit::Millilitres,
+        },
+        IngredientInfo {
+            id: String::from(SUGAR),
+            name: String::from("Sugar"),
+            unit: Unit::Grams,
+        },
+    ]
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
+    let res = match msg {
         // custom queries
-        QueryMsg::Owner {} => to_binary(&query_owner(deps)?),
+        QueryMsg::Owner {} => to_binary(&query_owner(deps, env)?),
+        QueryMsg::Config {} => to_binary(&query_config(deps, env)?),
+        QueryMsg::ShopInfo { coffee_shop_key } => {
+            to_binary(&query_shop_info(deps, env, coffee_shop_key)?)
+        }
+        QueryMsg::ListShops { start_after, limit } => {
+            to_binary(&query_list_shops(deps, env, start_after, limit)?)
+        }
         QueryMsg::Price {
             coffee_shop_key,
             id,
-        } => to_binary(&query_price(deps, coffee_shop_key, id)?),
+            size,
+        } => to_binary(&query_price(deps, coffee_shop_key, id, size)?),
         QueryMsg::Menu { coffee_shop_key } => to_binary(&query_menu(deps, coffee_shop_key)?),
         QueryMsg::Recipes { coffee_shop_key } => to_binary(&query_recipes(deps, coffee_shop_key)?),
         QueryMsg::Ingredients { coffee_shop_key } => {
             to_binary(&query_ingredients(deps, coffee_shop_key)?)
         }
+        QueryMsg::Availability { coffee_shop_key } => {
+            to_binary(&query_availability(deps, coffee_shop_key)?)
+        }
+        QueryMsg::Order { order_id } => to_binary(&query_order(deps, order_id)?),
+        QueryMsg::CustomerOrders {
+            customer,
+            start_after,
+            limit,
+        } => to_binary(&query_customer_orders(deps, customer, start_after, limit)?),
+        QueryMsg::ShopQueue {
+            coffee_shop_key,
+            start_after,
+            limit,
+        } => to_binary(&query_shop_queue(deps, coffee_shop_key, start_after, limit)?),
+        QueryMsg::Ledger { coffee_shop_key } => to_binary(&query_ledger(deps, coffee_shop_key)?),
+        QueryMsg::Withdrawals {
+            coffee_shop_key,
+            start_after,
+            limit,
+        } => to_binary(&query_withdrawals(
+            deps,
+            coffee_shop_key,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::Reconcile { token } => to_binary(&query_reconcile(deps, env, token)?),
+        QueryMsg::Roles {
+            coffee_shop_key,
+            start_after,
+            limit,
+        } => to_binary(&query_roles(deps, coffee_shop_key, start_after, limit)?),
+        QueryMsg::SimulatePurchase {
+            coffee_shop_key,
+            item,
+            size,
+            modifiers,
+            amount,
+        } => to_binary(&query_simulate_purchase(
+            deps,
+            env,
+            coffee_shop_key,
+            OrderLine {
+                item,
+                size,
+                modifiers,
+                quantity: amount,
+                expected_price: None,
+            },
+        )?),
         QueryMsg::Balance { contract_address, address} => to_binary(&query_token_balance(&deps.querier, contract_address,address)?),
-    }
+    };
+    Ok(res?)
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -165,280 +305,4149 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
+        ExecuteMsg::CreateShop {
+            shop_key,
+            owner,
+            menu,
+            recipes,
+        } => create_shop(deps, info, shop_key, owner, menu, recipes),
+        ExecuteMsg::GrantRole {
+            coffee_shop_key,
+            addr,
+            role,
+        } => grant_role(deps, info, coffee_shop_key, addr, role),
+        ExecuteMsg::RevokeRole {
+            coffee_shop_key,
+            addr,
+            role,
+        } => revoke_role(deps, info, coffee_shop_key, addr, role),
+        ExecuteMsg::SetShopActive {
+            coffee_shop_key,
+            active,
+        } => set_shop_active(deps, info, coffee_shop_key, active),
+        ExecuteMsg::AddMenuItem {
+            coffee_shop_key,
+            cup,
+            recipe,
+        } => add_menu_item(deps, info, coffee_shop_key, cup, recipe),
+        ExecuteMsg::UpdateMenuItem {
+            coffee_shop_key,
+            id,
+            cup,
+            recipe,
+        } => update_menu_item(deps, info, coffee_shop_key, id, cup, recipe),
+        ExecuteMsg::RemoveMenuItem {
+            coffee_shop_key,
+            id,
+        } => remove_menu_item(deps, info, coffee_shop_key, id),
+        ExecuteMsg::ReorderMenu {
+            coffee_shop_key,
+            ids,
+        } => reorder_menu(deps, info, coffee_shop_key, ids),
+        ExecuteMsg::SetModifiers {
+            coffee_shop_key,
+            id,
+            modifiers,
+        } => set_modifiers(deps, info, coffee_shop_key, id, modifiers),
         ExecuteMsg::SetPrice {
             coffee_shop_key,
             id,
+            size,
+            price,
+        } => set_price(deps, info, coffee_shop_key, id, size, price),
+        ExecuteMsg::SetNativePrice {
+            coffee_shop_key,
+            id,
+            size,
             price,
-        } => set_price(deps, info, coffee_shop_key, id, price),
+        } => set_native_price(deps, info, coffee_shop_key, id, size, price),
+        ExecuteMsg::RegisterIngredient {
+            coffee_shop_key,
+            ingredient,
+        } => register_ingredient(deps, info, coffee_shop_key, ingredient),
         ExecuteMsg::LoadIngredients {
             coffee_shop_key,
             portions,
         } => load_ingredients(deps, info, coffee_shop_key, portions),
+        ExecuteMsg::WriteOffIngredients {
+            coffee_shop_key,
+            portions,
+            reason,
+        } => write_off_ingredients(deps, info, _env, coffee_shop_key, portions, reason),
         ExecuteMsg::BuyCoffee {
             coffee_shop_key,
             id,
+            size,
+            modifiers,
+            amount,
+            expected_price,
+            max_total,
+        } => buy_coffee(
+            deps,
+            info,
+            _env,
+            coffee_shop_key,
+            OrderLine {
+                item: id,
+                size,
+                modifiers,
+                quantity: amount,
+                expected_price,
+            },
+            max_total,
+        ),
+        ExecuteMsg::PlaceOrder {
+            coffee_shop_key,
+            lines,
+            max_total,
+        } => place_order(
+            deps,
+            info,
+            _env,
+            coffee_shop_key,
+            lines,
+            max_total,
+            "place_order",
+        ),
+        ExecuteMsg::StartBrewing { order_id } => {
+            advance_order(deps, info, order_id, OrderStatus::Brewing)
+        }
+        ExecuteMsg::MarkOrderReady { order_id } => {
+            advance_order(deps, info, order_id, OrderStatus::Ready)
+        }
+        ExecuteMsg::MarkOrderPickedUp { order_id } => {
+            advance_order(deps, info, order_id, OrderStatus::PickedUp)
+        }
+        ExecuteMsg::CancelOrder { order_id, reason } => {
+            cancel_order(deps, info, order_id, reason)
+        }
+        ExecuteMsg::RefundOrder {
+            order_id,
+            restore_ingredients,
+            reason,
+        } => refund_order(deps, info, order_id, restore_ingredients, reason),
+        ExecuteMsg::ReclaimPayment { order_id } => reclaim_payment(deps, info, _env, order_id),
+        ExecuteMsg::SetOrderTimeout { seconds } => set_order_timeout(deps, info, seconds),
+        ExecuteMsg::UpdateConfig {
+            token_addr,
+            default_cup_weight,
+            fee,
+        } => update_config(deps, info, token_addr, default_cup_weight, fee),
+        ExecuteMsg::Pause {
+            coffee_shop_key,
+            reason,
+            expires,
+        } => pause(deps, info, _env, coffee_shop_key, reason, expires),
+        ExecuteMsg::Unpause { coffee_shop_key } => unpause(deps, info, coffee_shop_key),
+        ExecuteMsg::ProposeNewOwner { owner, expires } => {
+            propose_new_owner(deps, info, _env, owner, expires)
+        }
+        ExecuteMsg::AcceptOwnership {} => accept_ownership(deps, info, _env),
+        ExecuteMsg::CancelOwnershipProposal {} => cancel_ownership_proposal(deps, info),
+        ExecuteMsg::RenounceOwnership {} => renounce_ownership(deps, info),
+        ExecuteMsg::TransferAllTokens {
+            coffee_shop_key,
+            token,
+        } => transfer_tokens_to_owner(deps, info, _env, coffee_shop_key, token),
+        ExecuteMsg::TransferAllNativeTokens {
+            coffee_shop_key,
+            denom,
+        } => transfer_native_tokens_to_owner(deps, info, _env, coffee_shop_key, denom),
+        ExecuteMsg::Withdraw {
+            coffee_shop_key,
+            token,
             amount,
-        } => buy_coffee(deps, info, _env, coffee_shop_key, id, amount),
-        ExecuteMsg::TransferAllTokens {} => transfer_tokens_to_owner(deps, info, _env),
+            recipient,
+        } => withdraw(deps, info, _env, coffee_shop_key, token, amount, recipient),
+        ExecuteMsg::Receive(msg) => receive_cw20(deps, info, _env, msg),
     }
 }
 
-pub fn buy_coffee(
+pub fn create_shop(
     deps: DepsMut,
     info: MessageInfo,
-    env: Env,
-    coffee_shop_key: String,
-    id: Uint128,
-    cup_amount: Uint128,
+    shop_key: String,
+    owner: Addr,
+    mut menu: Vec<CoffeeCup>,
+    recipes: Vec<CoffeeRecipe>,
 ) -> Result<Response, ContractError> {
-    let coffee_state = COFFEE_STATE.load(deps.storage, coffee_shop_key.clone())?;
+    let state = STATE.load(deps.storage)?;
+    if !state.is_owner(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    let _id = id.u128() as usize;
-    if _id == 0 || _id > coffee_state.menu.len() {
+    if COFFEE_STATE.has(deps.storage, shop_key.clone()) {
+        return Err(ContractError::ShopAlreadyExists {});
+    }
+    // every menu item needs its recipe
+    if shop_key.is_empty() || menu.len() != recipes.len() {
         return Err(ContractError::InvalidParam {});
     }
-
-    let cup_price = coffee_state.menu[_id - 1].price;
-
-    // check is enough ingredients for order
-    let recipe = coffee_state.recipes[_id - 1].clone();
-    let total_ingredients_weight = cup_amount.mul(Uint128::new(AVERAGE_CUP_WEIGHT));
-
-    let is_enough_ingredients = check_weight(
-        &recipe.ingredients,
-        &coffee_state.ingredient_portions,
-        total_ingredients_weight,
-        SHARE_PRECISION,
-    );
-    if !is_enough_ingredients {
-        return Err(ContractError::NotEnoughIngredients {});
+    let catalogue = default_ingredient_catalogue();
+    for (cup, recipe) in menu.iter_mut().zip(recipes.iter()) {
+        fill_default_weight(cup, state.default_cup_weight);
+        validate_cup(cup)?;
+        validate_recipe(recipe, &catalogue, SHARE_PRECISION)?;
     }
 
-    // transfer amount from sender to contract balance
-    let total = cup_amount.mul(cup_price);
+    let owner = deps.api.addr_validate(owner.as_ref())?;
+    let coffee_state = CoffeeState::new(owner.clone(), menu, recipes, catalogue);
+    save_new_shop(deps.storage, &shop_key, &coffee_state)?;
 
-    let state = STATE.load(deps.storage)?;
+    Ok(Response::new()
+        .add_attribute("method", "create_shop")
+        .add_attribute("shop_key", shop_key)
+        .add_attribute("owner", owner))
+}
 
-    let res = execute_transfer_from(
-        state.coffee_token_addr,
-        info.sender,
-        env.contract.address,
-        total,
-    )?;
+pub fn grant_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    addr: Addr,
+    role: Role,
+) -> Result<Response, ContractError> {
+    let coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Owner)?;
 
-    // decrease ingredients amount
-    COFFEE_STATE.update(
+    let addr = deps.api.addr_validate(addr.as_ref())?;
+    SHOP_ROLES.update(
         deps.storage,
-        coffee_shop_key,
-        |state| -> Result<_, ContractError> {
-            let mut val = state.unwrap();
-            for portion in val.ingredient_portions.iter_mut() {
-                for ingredient in recipe.ingredients.iter() {
-                    if ingredient.ingredient_type != portion.ingredient {
-                        continue;
-                    }
-                    portion.weight = portion
-                        .weight
-                        .checked_sub(calculate_total_ingredient_weight(
-                            total_ingredients_weight,
-                            ingredient.share,
-                            SHARE_PRECISION,
-                        ))
-                        .unwrap();
-                }
+        (coffee_shop_key, addr.clone()),
+        |roles| -> StdResult<_> {
+            let mut roles = roles.unwrap_or_default();
+            if !roles.contains(&role) {
+                roles.push(role);
             }
-            Ok(val)
+            Ok(roles)
         },
     )?;
 
-    Ok(res.add_attribute("method", "buy_coffee"))
+    Ok(Response::new()
+        .add_attribute("method", "grant_role")
+        .add_attribute("addr", addr)
+        .add_attribute("role", role.as_str()))
 }
 
-pub fn set_price(
+pub fn revoke_role(
     deps: DepsMut,
     info: MessageInfo,
     coffee_shop_key: String,
-    id: Uint128,
-    price: Uint128,
+    addr: Addr,
+    role: Role,
 ) -> Result<Response, ContractError> {
-    if info.sender != STATE.load(deps.storage)?.owner {
-        return Err(ContractError::Unauthorized {});
+    let coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Owner)?;
+
+    let mut roles = roles_of(deps.storage, &coffee_shop_key, &addr)?;
+    roles.retain(|granted| *granted != role);
+    let key = (coffee_shop_key, addr.clone());
+    if roles.is_empty() {
+        SHOP_ROLES.remove(deps.storage, key);
+    } else {
+        SHOP_ROLES.save(deps.storage, key, &roles)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "revoke_role")
+        .add_attribute("addr", addr)
+        .add_attribute("role", role.as_str()))
+}
+
+pub fn set_shop_active(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    active: bool,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Owner)?;
+
+    coffee_state.active = active;
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "set_shop_active")
+        .add_attribute("active", active.to_string()))
+}
+
+// a single line order
+pub fn buy_coffee(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    coffee_shop_key: String,
+    line: OrderLine,
+    max_total: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    place_order(
+        deps,
+        info,
+        env,
+        coffee_shop_key,
+        vec![line],
+        max_total,
+        "buy_coffee",
+    )
+}
+
+// paid in native coins when `funds` are attached, otherwise with a single cw20 TransferFrom
+pub fn place_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    coffee_shop_key: String,
+    lines: Vec<OrderLine>,
+    max_total: Option<Uint128>,
+    method: &str,
+) -> Result<Response, ContractError> {
+    if !info.funds.is_empty() {
+        return place_order_with_native(
+            deps,
+            info,
+            env,
+            coffee_shop_key,
+            lines,
+            max_total,
+            method,
+        );
     }
 
-    COFFEE_STATE.update(
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    check_not_paused(deps.storage, &coffee_state, &env.block)?;
+    let (sizes, used) = brew_order(&mut coffee_state, &lines)?;
+    let prices: Vec<Uint128> = sizes.iter().map(|cup_size| cup_size.price).collect();
+    let (line_totals, total) = order_totals(&lines, &prices, max_total)?;
+
+    let state = STATE.load(deps.storage)?;
+
+    COFFEE_STATE.save(deps.storage, coffee_shop_key.clone(), &coffee_state)?;
+    let order_id = record_order(
         deps.storage,
+        &env,
         coffee_shop_key,
-        |state| -> Result<_, ContractError> {
-            // TODO: check wether menu have already been init
-            let mut val = state.unwrap();
+        info.sender.clone(),
+        lines.clone(),
+        used,
+        Asset::token(state.coffee_token_addr.clone(), total),
+    )?;
 
-            let _id = id.u128() as usize;
+    // TransferFrom fails with an opaque cw20 error, so check the allowance up front
+    let allowance = query_token_allowance(
+        &deps.querier,
+        state.coffee_token_addr.clone(),
+        info.sender.clone(),
+        env.contract.address.clone(),
+    )?;
+    if allowance < total {
+        return Err(ContractError::NoAllowance {});
+    }
 
-            if _id == 0 || _id > val.menu.len() || price == Uint128::zero() {
-                return Err(ContractError::InvalidParam {});
-            }
-            val.menu[_id - 1].price = price;
-            Ok(val)
-        },
+    // transfer amount from sender to contract balance
+    let res = execute_transfer_from(
+        state.coffee_token_addr,
+        info.sender,
+        env.contract.address,
+        total,
     )?;
 
-    Ok(Response::new().add_attribute("method", "set_price"))
+    Ok(res
+        .add_attribute("method", method)
+        .add_attribute("order_id", order_id)
+        .add_attribute("total", total)
+        .add_attributes(line_attributes(&lines, &sizes, &line_totals)))
 }
 
-pub fn load_ingredients(
+fn place_order_with_native(
     deps: DepsMut,
     info: MessageInfo,
+    env: Env,
     coffee_shop_key: String,
-    portions: Vec<IngredientPortion>,
+    lines: Vec<OrderLine>,
+    max_total: Option<Uint128>,
+    method: &str,
 ) -> Result<Response, ContractError> {
-    if info.sender != STATE.load(deps.storage)?.owner {
-        return Err(ContractError::Unauthorized {});
+    // a single coin of the items' denom is expected
+    if info.funds.len() != 1 {
+        return Err(ContractError::InvalidParam {});
     }
+    let paid = info.funds[0].clone();
 
-    COFFEE_STATE.update(
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    check_not_paused(deps.storage, &coffee_state, &env.block)?;
+    let (sizes, used) = brew_order(&mut coffee_state, &lines)?;
+    let mut prices = Vec::with_capacity(lines.len());
+    for cup_size in sizes.iter() {
+        let cup_price = cup_size
+            .native_price
+            .as_ref()
+            .ok_or(ContractError::NativePaymentNotAccepted {})?;
+        if paid.denom != cup_price.denom {
+            return Err(ContractError::InvalidToken {});
+        }
+        prices.push(cup_price.amount);
+    }
+
+    let (line_totals, total) = order_totals(&lines, &prices, max_total)?;
+    if paid.amount < total {
+        return Err(ContractError::NotEnoughFunds {});
+    }
+
+    COFFEE_STATE.save(deps.storage, coffee_shop_key.clone(), &coffee_state)?;
+    let order_id = record_order(
         deps.storage,
+        &env,
         coffee_shop_key,
-        |state| -> Result<_, ContractError> {
-            let mut val = state.unwrap();
-            // TODO: eliminate loading ing-s duplicates. Refactor with map
-            for portion in portions {
-                if portion.weight == Uint128::zero() {
-                    return Err(ContractError::InvalidParam {});
-                }
-                for state_portion in val.ingredient_portions.iter_mut() {
-                    if portion.ingredient == state_portion.ingredient {
-                        state_portion.weight = state_portion.weight.add(portion.weight);
-                    }
-                }
-            }
-            Ok(val)
-        },
+        info.sender.clone(),
+        lines.clone(),
+        used,
+        Asset::native(Coin::new(total.u128(), paid.denom.clone())),
     )?;
 
-    Ok(Response::new().add_attribute("method", "set_price"))
+    let change = paid.amount.checked_sub(total)?;
+    let mut res = Response::new();
+    if !change.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(change.u128(), paid.denom),
+        });
+    }
+
+    Ok(res
+        .add_attribute("method", method)
+        .add_attribute("change", change)
+        .add_attribute("order_id", order_id)
+        .add_attribute("total", total)
+        .add_attributes(line_attributes(&lines, &sizes, &line_totals)))
 }
 
-pub fn transfer_tokens_to_owner(
+pub fn receive_cw20(
     deps: DepsMut,
     info: MessageInfo,
     env: Env,
+    cw20_msg: Cw20ReceiveMsg,
 ) -> Result<Response, ContractError> {
     let state = STATE.load(deps.storage)?;
-    let owner = state.owner;
 
-    if info.sender != owner {
-        return Err(ContractError::Unauthorized {});
+    // only the coffee token is accepted as payment
+    if info.sender != state.coffee_token_addr {
+        return Err(ContractError::InvalidToken {});
     }
+    let customer = deps.api.addr_validate(&cw20_msg.sender)?;
+
+    let (coffee_shop_key, lines, max_total, method) = match from_binary(&cw20_msg.msg)? {
+        ReceiveMsg::BuyCoffee {
+            coffee_shop_key,
+            id,
+            size,
+            modifiers,
+            amount,
+            expected_price,
+            max_total,
+        } => {
+            let line = OrderLine {
+                item: id,
+                size,
+                modifiers,
+                quantity: amount,
+                expected_price,
+            };
+            (coffee_shop_key, vec![line], max_total, "buy_coffee")
+        }
+        ReceiveMsg::PlaceOrder {
+            coffee_shop_key,
+            lines,
+            max_total,
+        } => (coffee_shop_key, lines, max_total, "place_order"),
+    };
 
-    let balance = query_token_balance(&deps.querier, state.coffee_token_addr.clone(),env.contract.address.clone())?;
-    if balance.is_zero() {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    check_not_paused(deps.storage, &coffee_state, &env.block)?;
+    let (sizes, used) = brew_order(&mut coffee_state, &lines)?;
+    let prices: Vec<Uint128> = sizes.iter().map(|cup_size| cup_size.price).collect();
+    let (line_totals, total) = order_totals(&lines, &prices, max_total)?;
+    if cw20_msg.amount < total {
         return Err(ContractError::NotEnoughFunds {});
     }
 
-    let res = execute_transfer(state.coffee_token_addr, owner, balance)?;
-    Ok(res.add_attribute("method", "transfer_tokens_to_owner"))
+    COFFEE_STATE.save(deps.storage, coffee_shop_key.clone(), &coffee_state)?;
+    let order_id = record_order(
+        deps.storage,
+        &env,
+        coffee_shop_key,
+        customer.clone(),
+        lines.clone(),
+        used,
+        Asset::token(state.coffee_token_addr.clone(), total),
+    )?;
+
+    // tokens are already on the contract balance, give back the change
+    let change = cw20_msg.amount.checked_sub(total)?;
+    let res = if change.is_zero() {
+        Response::new()
+    } else {
+        execute_transfer(state.coffee_token_addr, customer, change)?
+    };
+
+    Ok(res
+        .add_attribute("method", method)
+        .add_attribute("change", change)
+        .add_attribute("order_id", order_id)
+        .add_attribute("total", total)
+        .add_attributes(line_attributes(&lines, &sizes, &line_totals)))
 }
 
-fn query_owner(deps: Deps) -> StdResult<OwnerResponse> {
-    let state = STATE.load(deps.storage)?;
-    Ok(OwnerResponse { owner: state.owner })
+// keeps track of the drinks owed to the customer, the payment stays in escrow
+// until the order is picked up
+fn record_order(
+    storage: &mut dyn Storage,
+    env: &Env,
+    shop_key: String,
+    customer: Addr,
+    lines: Vec<OrderLine>,
+    ingredients: Vec<IngredientPortion>,
+    payment: Asset,
+) -> StdResult<Uint128> {
+    let order_timeout = STATE.load(storage)?.order_timeout;
+    add_escrow(storage, &payment)?;
+
+    let order = CoffeeOrder {
+        id: next_order_id(storage)?,
+        shop_key,
+        customer,
+        lines,
+        ingredients,
+        payment,
+        status: OrderStatus::Placed,
+        placed_at: env.block.time,
+        expires_at: env.block.time.plus_seconds(order_timeout),
+        reason: None,
+    };
+    order.save(storage)?;
+    Ok(order.id)
 }
 
-fn query_ingredients(deps: Deps, coffee_shop_key: String) -> StdResult<IngredientsResponse> {
-    let state = COFFEE_STATE.load(deps.storage, coffee_shop_key)?;
-    Ok(IngredientsResponse {
-        ingredients: state.ingredient_portions,
-    })
+// totals of the lines and the whole order for the given cup prices, checked against the
+// limits set by the customer so a price change can't charge more than they agreed to
+fn order_totals(
+    lines: &[OrderLine],
+    prices: &[Uint128],
+    max_total: Option<Uint128>,
+) -> Result<(Vec<Uint128>, Uint128), ContractError> {
+    let mut line_totals = Vec::with_capacity(lines.len());
+    for (line, price) in lines.iter().zip(prices.iter()) {
+        if let Some(expected_price) = line.expected_price {
+            if *price > expected_price {
+                return Err(ContractError::PriceAboveExpected {
+                    price: *price,
+                    expected_price,
+                });
+            }
+        }
+        line_totals.push(line.quantity.checked_mul(*price)?);
+    }
+
+    let total = line_totals
+        .iter()
+        .try_fold(Uint128::zero(), |total, line_total| total.checked_add(*line_total))?;
+    if let Some(max_total) = max_total {
+        if total > max_total {
+            return Err(ContractError::TotalAboveMax { total, max_total });
+        }
+    }
+    Ok((line_totals, total))
 }
 
-fn query_price(deps: Deps, coffee_shop_key: String, id: Uint128) -> StdResult<Uint128> {
-    let state = COFFEE_STATE.load(deps.storage, coffee_shop_key)?;
-    let _id = id.u128() as usize;
-    if _id == 0 || _id > state.menu.len() {
-        // return Err(NotFound {});
+// line_<n>_* attributes describe the n-th line of the order
+fn line_attributes(
+    lines: &[OrderLine],
+    sizes: &[CupSize],
+    line_totals: &[Uint128],
+) -> Vec<Attribute> {
+    let mut attributes = Vec::with_capacity(lines.len() * 5);
+    for (n, line) in lines.iter().enumerate() {
+        attributes.push(attr(format!("line_{}_item", n), line.item));
+        attributes.push(attr(format!("line_{}_size", n), sizes[n].name.clone()));
+        // empty attribute values are rejected by the chain
+        if !line.modifiers.is_empty() {
+            attributes.push(attr(format!("line_{}_modifiers", n), line.modifiers.join(",")));
+        }
+        attributes.push(attr(format!("line_{}_quantity", n), line.quantity));
+        attributes.push(attr(format!("line_{}_total", n), line_totals[n]));
     }
-    Ok( state.menu[_id - 1].price)
+    attributes
 }
 
-fn query_menu(deps: Deps, coffee_shop_key: String) -> StdResult<MenuResponse> {
-    let state = COFFEE_STATE.load(deps.storage, coffee_shop_key)?;
-    Ok(MenuResponse { menu: state.menu })
+// purchases stop while the contract or the shop is paused
+fn check_not_paused(
+    storage: &dyn Storage,
+    coffee_state: &CoffeeState,
+    block: &BlockInfo,
+) -> Result<(), ContractError> {
+    let state = STATE.load(storage)?;
+    if active_pause(state.pause, block).is_some() {
+        return Err(ContractError::ContractPaused {});
+    }
+    if active_pause(coffee_state.pause.clone(), block).is_some() {
+        return Err(ContractError::ShopPaused {});
+    }
+    Ok(())
 }
 
-fn query_recipes(deps: Deps, coffee_shop_key: String) -> StdResult<RecipesResponse> {
-    let state = COFFEE_STATE.load(deps.storage, coffee_shop_key)?;
-    Ok(RecipesResponse {
-        recipes: state.recipes,
-    })
+// expired pauses are left in storage until the next pause or unpause
+fn active_pause(pause: Option<PauseInfo>, block: &BlockInfo) -> Option<PauseInfo> {
+    pause.filter(|pause| pause.is_active(block))
 }
 
-#[cfg(test)]
-mod tests {
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+// checks every line against the menu, then the order against the stock, takes the
+// ingredients and returns the ordered sizes priced with the chosen modifiers along
+// with the ingredients taken
+fn brew_order(
+    coffee_state: &mut CoffeeState,
+    lines: &[OrderLine],
+) -> Result<(Vec<CupSize>, Vec<IngredientPortion>), ContractError> {
+    if !coffee_state.active {
+        return Err(ContractError::ShopInactive {});
+    }
+    let (sizes, brews) = customize_order(coffee_state, lines)?;
+    let needs = order_needs(&brews)?;
 
-    use super::*;
-    use cosmwasm_std::Addr;
+    // check is enough ingredients for the whole order
+    check_order_stock(&brews, &needs, &coffee_state.ingredient_portions)?;
 
-    #[test]
-    fn proper_instantiation() {
-        let mut deps = mock_dependencies(&[]);
-        let creator = String::from("creator");
-        let shop_key = "shop".to_string();
+    // decrease ingredients amount
+    for need in needs.iter() {
+        if let Some(portion) = coffee_state
+            .ingredient_portions
+            .iter_mut()
+            .find(|portion| portion.ingredient == need.ingredient)
+        {
+            portion.weight = portion.weight.checked_sub(need.weight)?;
+        }
+    }
 
-        let msg = InstantiateMsg {
-            token_addr: Addr::unchecked("addr"),
-            shop_key,
-        };
-        let info = mock_info(&creator, &[]);
+    Ok((sizes, needs))
+}
 
-        // make sure we can instantiate with this
-        let res = instantiate(deps.as_mut(), mock_env(), info, msg.clone()).unwrap();
-        assert_eq!(0, res.messages.len());
+// a customized recipe with the weight of all the cups of its line
+type Brew = (CoffeeRecipe, Uint128);
 
-        // owner
-        assert_eq!(
-            query_owner(deps.as_ref()).unwrap().owner,
-            Addr::unchecked(creator)
-        );
+// the ordered sizes priced with the chosen modifiers, and the customized recipe of
+// every line with the weight of its cups, shared by the purchase and its simulation
+fn customize_order(
+    coffee_state: &CoffeeState,
+    lines: &[OrderLine],
+) -> Result<(Vec<CupSize>, Vec<Brew>), ContractError> {
+    if lines.is_empty() {
+        return Err(ContractError::InvalidParam {});
     }
 
-    #[test]
-    fn set_price_test() {
-        let mut deps = mock_dependencies(&[]);
-        let creator = String::from("creator");
-        let shop_key = "shop".to_string();
+    let mut sizes = Vec::with_capacity(lines.len());
+    let mut brews = Vec::with_capacity(lines.len());
+    for line in lines {
+        if line.quantity.is_zero() {
+            return Err(ContractError::InvalidParam {});
+        }
+        let (cup_size, recipe) = customize_line(coffee_state, line)?;
+        brews.push((recipe, line.quantity.checked_mul(cup_size.weight)?));
+        sizes.push(cup_size);
+    }
+    Ok((sizes, brews))
+}
+
+// the combined ingredients the customized lines take
+fn order_needs(brews: &[Brew]) -> Result<Vec<IngredientPortion>, ContractError> {
+    let mut needs: Vec<IngredientPortion> = vec![];
+    for (recipe, weight) in brews {
+        add_ingredient_needs(&mut needs, &recipe.ingredients, *weight, SHARE_PRECISION)?;
+    }
+    Ok(needs)
+}
+
+// every line has to fit the stock on its own, then the combined needs so two lines
+// can't count the same stock
+fn check_order_stock(
+    brews: &[Brew],
+    needs: &[IngredientPortion],
+    portions: &[IngredientPortion],
+) -> Result<(), ContractError> {
+    for (recipe, weight) in brews {
+        check_weight(&recipe.ingredients, portions, *weight, SHARE_PRECISION)?;
+    }
+    check_stock(needs, portions)
+}
+
+// returns the ordered size priced with the line's modifiers and the customized recipe
+fn customize_line(
+    coffee_state: &CoffeeState,
+    line: &OrderLine,
+) -> Result<(CupSize, CoffeeRecipe), ContractError> {
+    let index = coffee_state
+        .item_index(line.item)
+        .ok_or(ContractError::MenuItemNotFound { id: line.item })?;
+    let item = &coffee_state.menu[index];
+    let mut cup_size = find_size(&item.cup, line.size.clone())?.clone();
+
+    let mut modifiers: Vec<&Modifier> = Vec::with_capacity(line.modifiers.len());
+    for (i, modifier_id) in line.modifiers.iter().enumerate() {
+        if line.modifiers[..i].contains(modifier_id) {
+            return Err(ContractError::InvalidParam {});
+        }
+        let modifier = item
+            .modifiers
+            .iter()
+            .find(|modifier| modifier.id == *modifier_id)
+            .ok_or_else(|| ContractError::UnknownModifier {
+                modifier: modifier_id.clone(),
+            })?;
+        cup_size.price = cup_size.price.checked_add(modifier.price_delta)?;
+        if let Some(native_price) = cup_size.native_price.as_mut() {
+            native_price.amount = native_price.amount.checked_add(modifier.native_price_delta)?;
+        }
+        modifiers.push(modifier);
+    }
+
+    // modifiers are checked one by one when stored, their sum only shows in the customized recipe
+    let recipe = apply_modifiers(&item.recipe, &modifiers);
+    validate_recipe(&recipe, &coffee_state.ingredients, SHARE_PRECISION)?;
+    Ok((cup_size, recipe))
+}
+
+fn find_size(cup: &CoffeeCup, size: Option<String>) -> Result<&CupSize, ContractError> {
+    let index = cup
+        .size_index(size.as_deref())
+        .ok_or_else(|| ContractError::UnknownSize {
+            size: size.unwrap_or_default(),
+        })?;
+    Ok(&cup.sizes[index])
+}
+
+// moves an order to the next status, done by the shop staff
+pub fn advance_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    order_id: Uint128,
+    status: OrderStatus,
+) -> Result<Response, ContractError> {
+    let mut order = load_order(deps.storage, order_id)?;
+    let mut coffee_state = load_shop(deps.storage, &order.shop_key)?;
+    require_role(deps.storage, &order.shop_key, &coffee_state, &info.sender, Role::Barista)?;
+    if !order.status.can_advance_to(&status) {
+        return Err(ContractError::InvalidOrderStatus {
+            order_id,
+            status: order.status.as_str().to_string(),
+        });
+    }
+
+    // a fulfilled order's payment becomes the shop's revenue
+    if status == OrderStatus::PickedUp {
+        release_escrow(deps.storage, &order.payment)?;
+        credit_ledger(deps.storage, &order.shop_key, &order.payment)?;
+        coffee_state.add_revenue(&order.payment)?;
+        COFFEE_STATE.save(deps.storage, order.shop_key.clone(), &coffee_state)?;
+    }
+
+    order.status = status;
+    order.save(deps.storage)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "advance_order")
+        .add_attribute("order_id", order_id)
+        .add_attribute("status", order.status.as_str()))
+}
+
+fn load_order(storage: &dyn Storage, order_id: Uint128) -> Result<CoffeeOrder, ContractError> {
+    ORDERS
+        .may_load(storage, U128Key::new(order_id.u128()))?
+        .ok_or(ContractError::OrderNotFound { order_id })
+}
+
+// the customer calls the order off before it's brewed, the ingredients go back to the stock
+pub fn cancel_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    order_id: Uint128,
+    reason: Option<String>,
+) -> Result<Response, ContractError> {
+    let order = load_order(deps.storage, order_id)?;
+    if info.sender != order.customer {
+        return Err(ContractError::Unauthorized {});
+    }
+    if order.status != OrderStatus::Placed {
+        return Err(ContractError::InvalidOrderStatus {
+            order_id,
+            status: order.status.as_str().to_string(),
+        });
+    }
+
+    let res = close_order(deps, order, OrderStatus::Cancelled, reason, true)?;
+    Ok(res.add_attribute("method", "cancel_order"))
+}
+
+// shop staff give the payment back at any stage of the order
+pub fn refund_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    order_id: Uint128,
+    restore_ingredients: bool,
+    reason: Option<String>,
+) -> Result<Response, ContractError> {
+    let order = load_order(deps.storage, order_id)?;
+    let coffee_state = load_shop(deps.storage, &order.shop_key)?;
+    require_role(deps.storage, &order.shop_key, &coffee_state, &info.sender, Role::Manager)?;
+    if !order.status.is_open() && order.status != OrderStatus::PickedUp {
+        return Err(ContractError::InvalidOrderStatus {
+            order_id,
+            status: order.status.as_str().to_string(),
+        });
+    }
+
+    let res = close_order(deps, order, OrderStatus::Refunded, reason, restore_ingredients)?;
+    Ok(res.add_attribute("method", "refund_order"))
+}
+
+// sends the payment back to the customer and closes the order with the given status
+fn close_order(
+    deps: DepsMut,
+    mut order: CoffeeOrder,
+    status: OrderStatus,
+    reason: Option<String>,
+    restore_ingredients: bool,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &order.shop_key)?;
+
+    // a picked up order was already paid out of escrow into the revenue
+    if order.status == OrderStatus::PickedUp {
+        debit_ledger(deps.storage, &order.shop_key, &order.payment)?;
+        coffee_state.remove_revenue(&order.payment)?;
+    } else {
+        release_escrow(deps.storage, &order.payment)?;
+    }
+
+    if restore_ingredients {
+        for used in order.ingredients.iter() {
+            if let Some(portion) = coffee_state
+                .ingredient_portions
+                .iter_mut()
+                .find(|portion| portion.ingredient == used.ingredient)
+            {
+                portion.weight = portion.weight.checked_add(used.weight)?;
+            }
+        }
+    }
+    COFFEE_STATE.save(deps.storage, order.shop_key.clone(), &coffee_state)?;
+
+    order.status = status;
+    order.reason = reason;
+    order.save(deps.storage)?;
+
+    let mut res = Response::new()
+        .add_message(order.payment.transfer_msg(&order.customer)?)
+        .add_attribute("order_id", order.id)
+        .add_attribute("amount", order.payment.amount)
+        .add_attribute("restore_ingredients", restore_ingredients.to_string());
+    if let Some(reason) = order.reason {
+        res = res.add_attribute("reason", reason);
+    }
+    Ok(res)
+}
+
+// the customer takes the payment back when the order isn't picked up in time
+pub fn reclaim_payment(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    order_id: Uint128,
+) -> Result<Response, ContractError> {
+    let mut order = load_order(deps.storage, order_id)?;
+    if info.sender != order.customer {
+        return Err(ContractError::Unauthorized {});
+    }
+    if !order.status.is_open() {
+        return Err(ContractError::InvalidOrderStatus {
+            order_id,
+            status: order.status.as_str().to_string(),
+        });
+    }
+    if env.block.time < order.expires_at {
+        return Err(ContractError::OrderNotExpired {
+            order_id,
+            expires_at: order.expires_at,
+        });
+    }
+
+    release_escrow(deps.storage, &order.payment)?;
+    order.status = OrderStatus::Cancelled;
+    order.save(deps.storage)?;
+
+    Ok(Response::new()
+        .add_message(order.payment.transfer_msg(&order.customer)?)
+        .add_attribute("method", "reclaim_payment")
+        .add_attribute("order_id", order_id)
+        .add_attribute("amount", order.payment.amount))
+}
+
+pub fn set_order_timeout(
+    deps: DepsMut,
+    info: MessageInfo,
+    seconds: u64,
+) -> Result<Response, ContractError> {
+    STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
+        if !state.is_owner(&info.sender) {
+            return Err(ContractError::Unauthorized {});
+        }
+        if seconds == 0 {
+            return Err(ContractError::InvalidParam {});
+        }
+        state.order_timeout = seconds;
+        Ok(state)
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("method", "set_order_timeout")
+        .add_attribute("seconds", seconds.to_string()))
+}
+
+pub fn update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    token_addr: Option<Addr>,
+    default_cup_weight: Option<Uint128>,
+    fee: Option<FeeConfig>,
+) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    if !state.is_owner(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut res = Response::new().add_attribute("method", "update_config");
+    if let Some(token_addr) = token_addr {
+        let token_addr = deps.api.addr_validate(token_addr.as_ref())?;
+        if token_addr != state.coffee_token_addr {
+            // the replaced token stays withdrawable, a token that comes back is current again
+            state.previous_token_addrs.retain(|addr| *addr != token_addr);
+            let previous = std::mem::replace(&mut state.coffee_token_addr, token_addr);
+            state.previous_token_addrs.push(previous);
+            res = res.add_attribute("token_addr", state.coffee_token_addr.clone());
+        }
+    }
+    if let Some(weight) = default_cup_weight {
+        if weight.is_zero() {
+            return Err(ContractError::InvalidParam {});
+        }
+        state.default_cup_weight = weight;
+        res = res.add_attribute("default_cup_weight", weight);
+    }
+    if let Some(fee) = fee {
+        if fee.rate >= Decimal::one() {
+            return Err(ContractError::InvalidFeeRate { rate: fee.rate });
+        }
+        res = res.add_attribute("fee_rate", fee.rate.to_string());
+        state.fee = if fee.rate.is_zero() {
+            None
+        } else {
+            Some(FeeConfig {
+                collector: deps.api.addr_validate(fee.collector.as_ref())?,
+                rate: fee.rate,
+            })
+        };
+    }
+    STATE.save(deps.storage, &state)?;
+
+    Ok(res)
+}
+
+pub fn pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    coffee_shop_key: Option<String>,
+    reason: Option<String>,
+    expires: Option<Timestamp>,
+) -> Result<Response, ContractError> {
+    if let Some(expires) = expires {
+        if expires <= env.block.time {
+            return Err(ContractError::InvalidParam {});
+        }
+    }
+    let pause = PauseInfo {
+        reason: reason.filter(|reason| !reason.is_empty()),
+        expires,
+    };
+    set_pause(deps, info, coffee_shop_key, Some(pause.clone()))?;
+
+    let mut res = Response::new().add_attribute("method", "pause");
+    if let Some(reason) = pause.reason {
+        res = res.add_attribute("reason", reason);
+    }
+    if let Some(expires) = pause.expires {
+        res = res.add_attribute("expires", expires.to_string());
+    }
+    Ok(res)
+}
+
+pub fn unpause(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: Option<String>,
+) -> Result<Response, ContractError> {
+    set_pause(deps, info, coffee_shop_key, None)?;
+    Ok(Response::new().add_attribute("method", "unpause"))
+}
+
+// the contract owner pauses every shop, shop managers pause their own shop
+fn set_pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: Option<String>,
+    pause: Option<PauseInfo>,
+) -> Result<(), ContractError> {
+    match coffee_shop_key {
+        None => {
+            let mut state = STATE.load(deps.storage)?;
+            if !state.is_owner(&info.sender) {
+                return Err(ContractError::Unauthorized {});
+            }
+            state.pause = pause;
+            STATE.save(deps.storage, &state)?;
+        }
+        Some(coffee_shop_key) => {
+            let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+            require_role(
+                deps.storage,
+                &coffee_shop_key,
+                &coffee_state,
+                &info.sender,
+                Role::Manager,
+            )?;
+            coffee_state.pause = pause;
+            COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn propose_new_owner(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    owner: Addr,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    if !state.is_owner(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let owner = deps.api.addr_validate(owner.as_ref())?;
+    if owner == info.sender {
+        return Err(ContractError::InvalidParam {});
+    }
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::OwnershipProposalExpired {});
+    }
+
+    // a new proposal replaces the previous one
+    OWNERSHIP_PROPOSAL.save(
+        deps.storage,
+        &OwnershipProposal {
+            owner: owner.clone(),
+            expires,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "propose_new_owner")
+        .add_attribute("proposed_owner", owner)
+        .add_attribute("expires", expires.to_string()))
+}
+
+pub fn accept_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+) -> Result<Response, ContractError> {
+    let proposal = OWNERSHIP_PROPOSAL
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoOwnershipProposal {})?;
+    if info.sender != proposal.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if proposal.expires.is_expired(&env.block) {
+        return Err(ContractError::OwnershipProposalExpired {});
+    }
+
+    let mut state = STATE.load(deps.storage)?;
+    let previous_owner = state.owner.replace(proposal.owner.clone());
+    STATE.save(deps.storage, &state)?;
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    let mut res = Response::new()
+        .add_attribute("method", "accept_ownership")
+        .add_attribute("owner", proposal.owner.clone());
+    if let Some(previous_owner) = previous_owner {
+        // the shops of the previous owner go along, a rotated key keeps its revenue
+        let shops = move_shops(deps.storage, &previous_owner, &proposal.owner)?;
+        res = res
+            .add_attribute("previous_owner", previous_owner)
+            .add_attribute("moved_shops", shops.len().to_string());
+    }
+    Ok(res)
+}
+
+pub fn cancel_ownership_proposal(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    if !STATE.load(deps.storage)?.is_owner(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    if OWNERSHIP_PROPOSAL.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoOwnershipProposal {});
+    }
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    Ok(Response::new().add_attribute("method", "cancel_ownership_proposal"))
+}
+
+pub fn renounce_ownership(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    if !state.is_owner(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    state.owner = None;
+    STATE.save(deps.storage, &state)?;
+    // nobody can take over a renounced contract
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    Ok(Response::new()
+        .add_attribute("method", "renounce_ownership")
+        .add_attribute("previous_owner", info.sender))
+}
+
+pub fn add_menu_item(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    mut cup: CoffeeCup,
+    recipe: CoffeeRecipe,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Manager)?;
+    fill_default_weight(&mut cup, STATE.load(deps.storage)?.default_cup_weight);
+    validate_cup(&cup)?;
+    validate_recipe(&recipe, &coffee_state.ingredients, SHARE_PRECISION)?;
+
+    let id = coffee_state.add_item(cup, recipe);
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "add_menu_item")
+        .add_attribute("id", id))
+}
+
+pub fn update_menu_item(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    id: Uint128,
+    mut cup: CoffeeCup,
+    recipe: CoffeeRecipe,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Manager)?;
+    fill_default_weight(&mut cup, STATE.load(deps.storage)?.default_cup_weight);
+    validate_cup(&cup)?;
+    validate_recipe(&recipe, &coffee_state.ingredients, SHARE_PRECISION)?;
+
+    let index = coffee_state
+        .item_index(id)
+        .ok_or(ContractError::MenuItemNotFound { id })?;
+    coffee_state.menu[index].cup = cup;
+    coffee_state.menu[index].recipe = recipe;
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "update_menu_item")
+        .add_attribute("id", id))
+}
+
+pub fn remove_menu_item(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    id: Uint128,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Manager)?;
+
+    let index = coffee_state
+        .item_index(id)
+        .ok_or(ContractError::MenuItemNotFound { id })?;
+    coffee_state.menu.remove(index);
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "remove_menu_item")
+        .add_attribute("id", id))
+}
+
+pub fn reorder_menu(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    ids: Vec<Uint128>,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Manager)?;
+
+    // the new order must list every item exactly once
+    if ids.len() != coffee_state.menu.len() {
+        return Err(ContractError::InvalidParam {});
+    }
+    let mut menu = Vec::with_capacity(ids.len());
+    for id in ids {
+        let index = coffee_state
+            .item_index(id)
+            .ok_or(ContractError::MenuItemNotFound { id })?;
+        if menu.iter().any(|item: &MenuItem| item.id == id) {
+            return Err(ContractError::InvalidParam {});
+        }
+        menu.push(coffee_state.menu[index].clone());
+    }
+    coffee_state.menu = menu;
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new().add_attribute("method", "reorder_menu"))
+}
+
+pub fn set_modifiers(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    id: Uint128,
+    modifiers: Vec<Modifier>,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Manager)?;
+    validate_modifiers(&modifiers, &coffee_state.ingredients)?;
+
+    let index = coffee_state
+        .item_index(id)
+        .ok_or(ContractError::MenuItemNotFound { id })?;
+    coffee_state.menu[index].modifiers = modifiers;
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "set_modifiers")
+        .add_attribute("id", id))
+}
+
+// sizes given without a weight take the configured default
+fn fill_default_weight(cup: &mut CoffeeCup, weight: Uint128) {
+    for cup_size in cup.sizes.iter_mut().filter(|cup_size| cup_size.weight.is_zero()) {
+        cup_size.weight = weight;
+    }
+}
+
+fn validate_cup(cup: &CoffeeCup) -> Result<(), ContractError> {
+    if cup.name.is_empty() || cup.sizes.is_empty() {
+        return Err(ContractError::InvalidParam {});
+    }
+    for (i, cup_size) in cup.sizes.iter().enumerate() {
+        if cup_size.name.is_empty() || cup_size.weight.is_zero() || cup_size.price.is_zero() {
+            return Err(ContractError::InvalidParam {});
+        }
+        if cup.sizes[..i].iter().any(|other| other.name == cup_size.name) {
+            return Err(ContractError::InvalidParam {});
+        }
+        if let Some(coin) = &cup_size.native_price {
+            if coin.amount.is_zero() || coin.denom.is_empty() {
+                return Err(ContractError::InvalidParam {});
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn set_price(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    id: Uint128,
+    size: Option<String>,
+    price: Uint128,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Manager)?;
+
+    let index = coffee_state
+        .item_index(id)
+        .ok_or(ContractError::MenuItemNotFound { id })?;
+    if price == Uint128::zero() {
+        return Err(ContractError::InvalidParam {});
+    }
+    let cup = &mut coffee_state.menu[index].cup;
+    let size_index = cup
+        .size_index(size.as_deref())
+        .ok_or_else(|| ContractError::UnknownSize {
+            size: size.unwrap_or_default(),
+        })?;
+    cup.sizes[size_index].price = price;
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new().add_attribute("method", "set_price"))
+}
+
+pub fn set_native_price(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    id: Uint128,
+    size: Option<String>,
+    price: Option<Coin>,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Manager)?;
+
+    let index = coffee_state
+        .item_index(id)
+        .ok_or(ContractError::MenuItemNotFound { id })?;
+    let mut cup = coffee_state.menu[index].cup.clone();
+    let size_index = cup
+        .size_index(size.as_deref())
+        .ok_or_else(|| ContractError::UnknownSize {
+            size: size.unwrap_or_default(),
+        })?;
+    cup.sizes[size_index].native_price = price;
+    validate_cup(&cup)?;
+    coffee_state.menu[index].cup = cup;
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new().add_attribute("method", "set_native_price"))
+}
+
+pub fn register_ingredient(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    ingredient: IngredientInfo,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(deps.storage, &coffee_shop_key, &coffee_state, &info.sender, Role::Manager)?;
+
+    if ingredient.id.is_empty() || ingredient.name.is_empty() {
+        return Err(ContractError::InvalidParam {});
+    }
+    if coffee_state
+        .ingredients
+        .iter()
+        .any(|info| info.id == ingredient.id)
+    {
+        return Err(ContractError::IngredientAlreadyRegistered {
+            ingredient: ingredient.id,
+        });
+    }
+
+    // registered ingredients start with an empty stock
+    coffee_state.ingredient_portions.push(IngredientPortion {
+        ingredient: ingredient.id.clone(),
+        weight: Uint128::zero(),
+    });
+    coffee_state.ingredients.push(ingredient.clone());
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "register_ingredient")
+        .add_attribute("ingredient", ingredient.id))
+}
+
+pub fn load_ingredients(
+    deps: DepsMut,
+    info: MessageInfo,
+    coffee_shop_key: String,
+    portions: Vec<IngredientPortion>,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(
+        deps.storage,
+        &coffee_shop_key,
+        &coffee_state,
+        &info.sender,
+        Role::InventoryClerk,
+    )?;
+
+    for portion in portions {
+        if portion.weight == Uint128::zero() {
+            return Err(ContractError::InvalidParam {});
+        }
+        let state_portion = coffee_state
+            .ingredient_portions
+            .iter_mut()
+            .find(|state_portion| state_portion.ingredient == portion.ingredient)
+            .ok_or(ContractError::UnknownIngredient {
+                ingredient: portion.ingredient,
+            })?;
+        state_portion.weight = state_portion.weight.checked_add(portion.weight)?;
+    }
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    Ok(Response::new().add_attribute("method", "load_ingredients"))
+}
+
+// takes spoiled or spilled ingredients out of the stock
+pub fn write_off_ingredients(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    coffee_shop_key: String,
+    portions: Vec<IngredientPortion>,
+    reason: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(
+        deps.storage,
+        &coffee_shop_key,
+        &coffee_state,
+        &info.sender,
+        Role::InventoryClerk,
+    )?;
+    // the stock only goes down through sales and write-offs while the shop is open
+    check_not_paused(deps.storage, &coffee_state, &env.block)?;
+
+    for portion in portions {
+        if portion.weight == Uint128::zero() {
+            return Err(ContractError::InvalidParam {});
+        }
+        let state_portion = coffee_state
+            .ingredient_portions
+            .iter_mut()
+            .find(|state_portion| state_portion.ingredient == portion.ingredient)
+            .ok_or_else(|| ContractError::UnknownIngredient {
+                ingredient: portion.ingredient.clone(),
+            })?;
+        if state_portion.weight < portion.weight {
+            return Err(ContractError::InsufficientIngredient {
+                ingredient: portion.ingredient,
+                needed: portion.weight,
+                available: state_portion.weight,
+            });
+        }
+        state_portion.weight = state_portion.weight.checked_sub(portion.weight)?;
+    }
+    COFFEE_STATE.save(deps.storage, coffee_shop_key, &coffee_state)?;
+
+    let mut res = Response::new().add_attribute("method", "write_off_ingredients");
+    if let Some(reason) = reason.filter(|reason| !reason.is_empty()) {
+        res = res.add_attribute("reason", reason);
+    }
+    Ok(res)
+}
+
+pub fn transfer_tokens_to_owner(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    coffee_shop_key: Option<String>,
+    token: Option<Addr>,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let token = token.unwrap_or(state.coffee_token_addr);
+    let asset_info = AssetInfo::Token {
+        contract_addr: token,
+    };
+    let res = withdraw_to_owner(deps, info, env, coffee_shop_key, asset_info)?;
+    Ok(res.add_attribute("method", "transfer_tokens_to_owner"))
+}
+
+pub fn transfer_native_tokens_to_owner(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    coffee_shop_key: Option<String>,
+    denom: String,
+) -> Result<Response, ContractError> {
+    let asset_info = AssetInfo::NativeToken { denom };
+    let res = withdraw_to_owner(deps, info, env, coffee_shop_key, asset_info)?;
+    Ok(res.add_attribute("method", "transfer_native_tokens_to_owner"))
+}
+
+// pays the ledger of the shop out to the shop owner, done by the shop owner or treasurer,
+// without a shop the contract owner takes the balance that isn't owed to any shop or held
+// for open orders
+fn withdraw_to_owner(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    coffee_shop_key: Option<String>,
+    asset_info: AssetInfo,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    check_payment_token(&state, &asset_info)?;
+
+    match coffee_shop_key {
+        Some(key) => {
+            let coffee_state = load_shop(deps.storage, &key)?;
+            require_role(
+                deps.storage,
+                &key,
+                &coffee_state,
+                &info.sender,
+                Role::Treasurer,
+            )?;
+            let amount = ledger_balance(deps.storage, &key, &asset_info)?;
+            let withdrawn = Asset {
+                info: asset_info,
+                amount,
+            };
+            pay_from_ledger(
+                deps,
+                &env,
+                &state,
+                key,
+                withdrawn,
+                coffee_state.owner,
+                info.sender,
+            )
+        }
+        None => {
+            if !state.is_owner(&info.sender) {
+                return Err(ContractError::Unauthorized {});
+            }
+            let balance = contract_balance(deps.as_ref(), &env, &asset_info)?;
+            let owed = ledger_total(deps.storage, &asset_info)?
+                .checked_add(escrowed(deps.storage, &asset_info)?)?;
+            let withdrawn = Asset {
+                info: asset_info,
+                amount: balance.saturating_sub(owed),
+            };
+            pay_out(&state, withdrawn, &info.sender)
+        }
+    }
+}
+
+pub fn withdraw(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    coffee_shop_key: String,
+    token: AssetInfo,
+    amount: Uint128,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let coffee_state = load_shop(deps.storage, &coffee_shop_key)?;
+    require_role(
+        deps.storage,
+        &coffee_shop_key,
+        &coffee_state,
+        &info.sender,
+        Role::Treasurer,
+    )?;
+    check_payment_token(&state, &token)?;
+    if amount.is_zero() {
+        return Err(ContractError::InvalidParam {});
+    }
+
+    let recipient = deps.api.addr_validate(recipient.as_ref())?;
+    let withdrawn = Asset {
+        info: token,
+        amount,
+    };
+    let res = pay_from_ledger(
+        deps,
+        &env,
+        &state,
+        coffee_shop_key,
+        withdrawn,
+        recipient,
+        info.sender,
+    )?;
+    Ok(res.add_attribute("method", "withdraw"))
+}
+
+// only the payment token and the tokens it replaced are withdrawn as cw20
+fn check_payment_token(state: &State, asset_info: &AssetInfo) -> Result<(), ContractError> {
+    match asset_info {
+        AssetInfo::Token { contract_addr } if !state.is_payment_token(contract_addr) => {
+            Err(ContractError::InvalidToken {})
+        }
+        _ => Ok(()),
+    }
+}
+
+// takes the amount from the ledger of the shop, pays it out and records the withdrawal
+fn pay_from_ledger(
+    deps: DepsMut,
+    env: &Env,
+    state: &State,
+    shop_key: String,
+    withdrawn: Asset,
+    recipient: Addr,
+    withdrawn_by: Addr,
+) -> Result<Response, ContractError> {
+    debit_ledger(deps.storage, &shop_key, &withdrawn)?;
+    let res = pay_out(state, withdrawn.clone(), &recipient)?;
+
+    let withdrawal = Withdrawal {
+        id: next_withdrawal_id(deps.storage)?,
+        shop_key,
+        fee: state.split_fee(withdrawn.amount).0,
+        asset: withdrawn,
+        recipient,
+        withdrawn_by,
+        time: env.block.time,
+    };
+    withdrawal.save(deps.storage)?;
+
+    Ok(res
+        .add_attribute("shop_key", withdrawal.shop_key)
+        .add_attribute("withdrawal_id", withdrawal.id))
+}
+
+// sends the amount less the fee to the recipient and the fee to the fee collector
+fn pay_out(state: &State, withdrawn: Asset, recipient: &Addr) -> Result<Response, ContractError> {
+    if withdrawn.amount.is_zero() {
+        return Err(ContractError::NotEnoughFunds {});
+    }
+
+    let (fee, amount) = state.split_fee(withdrawn.amount);
+    let payout = Asset {
+        info: withdrawn.info.clone(),
+        amount,
+    };
+    let mut res = Response::new()
+        .add_message(payout.transfer_msg(recipient)?)
+        .add_attribute("recipient", recipient)
+        .add_attribute("amount", amount);
+    if let Some(config) = state.fee.as_ref().filter(|_| !fee.is_zero()) {
+        let fee = Asset {
+            info: withdrawn.info,
+            amount: fee,
+        };
+        res = res
+            .add_message(fee.transfer_msg(&config.collector)?)
+            .add_attribute("fee", fee.amount);
+    }
+    Ok(res)
+}
+
+// what the contract holds of the asset, a failing token query is an error rather than nothing
+fn contract_balance(deps: Deps, env: &Env, asset_info: &AssetInfo) -> StdResult<Uint128> {
+    match asset_info {
+        AssetInfo::Token { contract_addr } => query_token_balance(
+            &deps.querier,
+            contract_addr.clone(),
+            env.contract.address.clone(),
+        ),
+        AssetInfo::NativeToken { denom } => Ok(deps
+            .querier
+            .query_balance(env.contract.address.clone(), denom.clone())?
+            .amount),
+    }
+}
+
+fn query_owner(deps: Deps, env: Env) -> StdResult<OwnerResponse> {
+    let state = STATE.load(deps.storage)?;
+    // an expired proposal can't be accepted anymore
+    let proposal = OWNERSHIP_PROPOSAL
+        .may_load(deps.storage)?
+        .filter(|proposal| !proposal.expires.is_expired(&env.block));
+    Ok(OwnerResponse {
+        owner: state.owner,
+        pending_owner: proposal.as_ref().map(|proposal| proposal.owner.clone()),
+        pending_expires: proposal.map(|proposal| proposal.expires),
+    })
+}
+
+fn query_config(deps: Deps, env: Env) -> StdResult<ConfigResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(ConfigResponse {
+        owner: state.owner,
+        coffee_token_addr: state.coffee_token_addr,
+        previous_token_addrs: state.previous_token_addrs,
+        order_timeout: state.order_timeout,
+        pause: active_pause(state.pause, &env.block),
+        default_cup_weight: state.default_cup_weight,
+        fee: state.fee,
+    })
+}
+
+fn query_ledger(deps: Deps, coffee_shop_key: String) -> StdResult<LedgerResponse> {
+    let balances = LEDGER
+        .prefix(coffee_shop_key)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, asset)| asset))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(LedgerResponse { balances })
+}
+
+fn query_reconcile(deps: Deps, env: Env, token: Option<Addr>) -> StdResult<ReconcileResponse> {
+    let token = match token {
+        Some(token) => token,
+        None => STATE.load(deps.storage)?.coffee_token_addr,
+    };
+    let asset_info = AssetInfo::Token {
+        contract_addr: token.clone(),
+    };
+    let balance = contract_balance(deps, &env, &asset_info)?;
+    let ledger = ledger_total(deps.storage, &asset_info)?;
+    let escrowed = escrowed(deps.storage, &asset_info)?;
+    let owed = ledger.checked_add(escrowed)?;
+    Ok(ReconcileResponse {
+        token,
+        balance,
+        ledger,
+        escrowed,
+        surplus: balance.saturating_sub(owed),
+        shortfall: owed.saturating_sub(balance),
+    })
+}
+
+fn query_shop_info(
+    deps: Deps,
+    env: Env,
+    coffee_shop_key: String,
+) -> Result<ShopInfoResponse, ContractError> {
+    let state = load_shop(deps.storage, &coffee_shop_key)?;
+    Ok(to_shop_info(coffee_shop_key, state, &env.block))
+}
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+fn query_list_shops(
+    deps: Deps,
+    env: Env,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ShopsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let shops = COFFEE_STATE
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (key, state) = item?;
+            let shop_key = String::from_utf8(key).map_err(StdError::invalid_utf8)?;
+            Ok(to_shop_info(shop_key, state, &env.block))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(ShopsResponse { shops })
+}
+
+fn query_order(deps: Deps, order_id: Uint128) -> StdResult<CoffeeOrder> {
+    ORDERS.load(deps.storage, U128Key::new(order_id.u128()))
+}
+
+// open orders of the customer, oldest first
+fn query_customer_orders(
+    deps: Deps,
+    customer: Addr,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+) -> StdResult<OrdersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| Bound::exclusive(U128Key::new(id.u128())));
+
+    let orders = CUSTOMER_ORDERS
+        .prefix(customer)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|key| query_order(deps, order_id_from_key(&key)?))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(OrdersResponse { orders })
+}
+
+// open orders of the shop in the order they were placed
+fn query_shop_queue(
+    deps: Deps,
+    coffee_shop_key: String,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+) -> StdResult<OrdersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| Bound::exclusive(U128Key::new(id.u128())));
+
+    let orders = SHOP_QUEUE
+        .prefix(coffee_shop_key)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|key| query_order(deps, order_id_from_key(&key)?))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(OrdersResponse { orders })
+}
+
+fn query_withdrawals(
+    deps: Deps,
+    coffee_shop_key: String,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+) -> StdResult<WithdrawalsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| Bound::exclusive(U128Key::new(id.u128())));
+
+    let withdrawals = WITHDRAWALS
+        .prefix(coffee_shop_key)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, withdrawal)| withdrawal))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(WithdrawalsResponse { withdrawals })
+}
+
+fn query_roles(
+    deps: Deps,
+    coffee_shop_key: String,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+) -> StdResult<RolesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|addr| Bound::exclusive(addr.as_bytes()));
+
+    let staff = SHOP_ROLES
+        .prefix(coffee_shop_key)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (key, roles) = item?;
+            let addr = String::from_utf8(key).map_err(StdError::invalid_utf8)?;
+            Ok(StaffRoles {
+                addr: Addr::unchecked(addr),
+                roles,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(RolesResponse { staff })
+}
+
+fn to_shop_info(shop_key: String, state: CoffeeState, block: &BlockInfo) -> ShopInfoResponse {
+    ShopInfoResponse {
+        shop_key,
+        owner: state.owner,
+        item_count: state.menu.len() as u64,
+        active: state.active,
+        pause: active_pause(state.pause, block),
+        revenue: state.revenue,
+        native_revenue: state.native_revenue,
+    }
+}
+
+fn query_ingredients(
+    deps: Deps,
+    coffee_shop_key: String,
+) -> Result<IngredientsResponse, ContractError> {
+    let state = load_shop(deps.storage, &coffee_shop_key)?;
+    Ok(IngredientsResponse {
+        ingredients: state.ingredient_portions,
+        catalogue: state.ingredients,
+    })
+}
+
+fn query_price(
+    deps: Deps,
+    coffee_shop_key: String,
+    id: Uint128,
+    size: Option<String>,
+) -> Result<Uint128, ContractError> {
+    let state = load_shop(deps.storage, &coffee_shop_key)?;
+    let index = state
+        .item_index(id)
+        .ok_or(ContractError::MenuItemNotFound { id })?;
+    Ok(find_size(&state.menu[index].cup, size)?.price)
+}
+
+// quotes a purchase without executing it, using the same pricing and stock checks
+fn query_simulate_purchase(
+    deps: Deps,
+    env: Env,
+    coffee_shop_key: String,
+    line: OrderLine,
+) -> Result<SimulatePurchaseResponse, ContractError> {
+    let state = load_shop(deps.storage, &coffee_shop_key)?;
+
+    let lines = vec![line];
+    let (sizes, brews) = customize_order(&state, &lines)?;
+    let needs = order_needs(&brews)?;
+    let cup_size = sizes[0].clone();
+    let (_, total) = order_totals(&lines, &[cup_size.price], None)?;
+    let native_total = match cup_size.native_price {
+        Some(price) => {
+            let (_, amount) = order_totals(&lines, &[price.amount], None)?;
+            Some(Coin::new(amount.u128(), price.denom))
+        }
+        None => None,
+    };
+
+    let stock = check_order_stock(&brews, &needs, &state.ingredient_portions);
+    let enough_stock = stock.is_ok();
+    let short_ingredients: Vec<String> = needs
+        .iter()
+        .filter(|need| {
+            check_stock(std::slice::from_ref(*need), &state.ingredient_portions).is_err()
+        })
+        .map(|need| need.ingredient.clone())
+        .collect();
+    let blocked = check_not_paused(deps.storage, &state, &env.block).and_then(|_| {
+        if !state.active {
+            return Err(ContractError::ShopInactive {});
+        }
+        stock
+    });
+
+    Ok(SimulatePurchaseResponse {
+        size: cup_size.name,
+        price: cup_size.price,
+        total,
+        native_total,
+        enough_stock,
+        ingredients: needs,
+        short_ingredients,
+        reason: blocked.err().map(|err| err.to_string()),
+    })
+}
+
+fn query_menu(deps: Deps, coffee_shop_key: String) -> Result<MenuResponse, ContractError> {
+    let state = load_shop(deps.storage, &coffee_shop_key)?;
+    let menu = state
+        .menu
+        .iter()
+        .map(|item| MenuEntry {
+            item: item.clone(),
+            available: item_availability(&state, item)
+                .sizes
+                .iter()
+                .any(|size| !size.cups.is_zero()),
+        })
+        .collect();
+    Ok(MenuResponse { menu })
+}
+
+fn query_availability(
+    deps: Deps,
+    coffee_shop_key: String,
+) -> Result<AvailabilityResponse, ContractError> {
+    let state = load_shop(deps.storage, &coffee_shop_key)?;
+    Ok(AvailabilityResponse {
+        items: state
+            .menu
+            .iter()
+            .map(|item| item_availability(&state, item))
+            .collect(),
+    })
+}
+
+// cups of every size of the item the shop's stock allows, without modifiers
+fn item_availability(coffee_state: &CoffeeState, item: &MenuItem) -> ItemAvailability {
+    ItemAvailability {
+        id: item.id,
+        name: item.cup.name.clone(),
+        sizes: item
+            .cup
+            .sizes
+            .iter()
+            .map(|size| {
+                let (cups, limiting_ingredient) = max_cups(
+                    &item.recipe.ingredients,
+                    &coffee_state.ingredient_portions,
+                    size.weight,
+                    SHARE_PRECISION,
+                );
+                SizeAvailability {
+                    size: size.name.clone(),
+                    cups,
+                    limiting_ingredient,
+                }
+            })
+            .collect(),
+    }
+}
+
+fn query_recipes(deps: Deps, coffee_shop_key: String) -> Result<RecipesResponse, ContractError> {
+    let state = load_shop(deps.storage, &coffee_shop_key)?;
+    Ok(RecipesResponse {
+        recipes: state
+            .menu
+            .into_iter()
+            .map(|item| RecipeInfo {
+                id: item.id,
+                recipe: item.recipe,
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+
+    use super::*;
+    use cosmwasm_std::{attr, CosmosMsg};
+    use crate::products::ModifierEffect;
+
+    fn fulfill_order(mut deps: DepsMut, info: MessageInfo, order_id: Uint128) {
+        for msg in [
+            ExecuteMsg::StartBrewing { order_id },
+            ExecuteMsg::MarkOrderReady { order_id },
+            ExecuteMsg::MarkOrderPickedUp { order_id },
+        ]
+        .iter()
+        {
+            execute(deps.branch(), mock_env(), info.clone(), msg.clone()).unwrap();
+        }
+    }
+
+    fn load_all_ingredients(deps: DepsMut, info: MessageInfo, shop_key: &str, weight: Uint128) {
+        let portions = default_ingredient_catalogue()
+            .into_iter()
+            .map(|info| IngredientPortion {
+                ingredient: info.id,
+                weight,
+            })
+            .collect();
+        let msg = ExecuteMsg::LoadIngredients {
+            coffee_shop_key: shop_key.to_string(),
+            portions,
+        };
+        execute(deps, mock_env(), info, msg).unwrap();
+    }
+
+    #[test]
+    fn proper_instantiation() {
+        let mut deps = mock_dependencies(&[]);
+        let creator = String::from("creator");
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("addr"),
+            shop_key,
+        };
+        let info = mock_info(&creator, &[]);
+
+        // make sure we can instantiate with this
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg.clone()).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // owner
+        assert_eq!(
+            query_owner(deps.as_ref(), mock_env()).unwrap().owner,
+            Some(Addr::unchecked(creator))
+        );
+    }
+
+    #[test]
+    fn ownership_transfer_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let new_owner = mock_info("new_owner", &[]);
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: "shop".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let propose = |expires: Option<Expiration>| ExecuteMsg::ProposeNewOwner {
+            owner: Addr::unchecked("new_owner"),
+            expires,
+        };
+        let accept = ExecuteMsg::AcceptOwnership {};
+
+        // only the owner proposes and only the proposed owner accepts
+        let err = execute(deps.as_mut(), mock_env(), new_owner.clone(), propose(None)).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err =
+            execute(deps.as_mut(), mock_env(), new_owner.clone(), accept.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NoOwnershipProposal {});
+
+        let env = mock_env();
+        let expires = Expiration::AtHeight(env.block.height + 10);
+        execute(deps.as_mut(), env.clone(), owner.clone(), propose(Some(expires))).unwrap();
+        let res = query_owner(deps.as_ref(), env.clone()).unwrap();
+        assert_eq!(res.owner, Some(Addr::unchecked("creator")));
+        assert_eq!(res.pending_owner, Some(Addr::unchecked("new_owner")));
+        assert_eq!(res.pending_expires, Some(expires));
+
+        let err = execute(deps.as_mut(), env.clone(), owner.clone(), accept.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        // the proposal can't be accepted once it expired
+        let mut late = env.clone();
+        late.block.height += 10;
+        assert_eq!(query_owner(deps.as_ref(), late.clone()).unwrap().pending_owner, None);
+        let err = execute(deps.as_mut(), late, new_owner.clone(), accept.clone()).unwrap_err();
+        assert_eq!(err, ContractError::OwnershipProposalExpired {});
+
+        // a cancelled proposal is gone
+        execute(deps.as_mut(), env.clone(), owner.clone(), propose(None)).unwrap();
+        let msg = ExecuteMsg::CancelOwnershipProposal {};
+        execute(deps.as_mut(), env.clone(), owner.clone(), msg).unwrap();
+        let err =
+            execute(deps.as_mut(), env.clone(), new_owner.clone(), accept.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NoOwnershipProposal {});
+
+        execute(deps.as_mut(), env.clone(), owner.clone(), propose(None)).unwrap();
+        execute(deps.as_mut(), env.clone(), new_owner.clone(), accept).unwrap();
+        let res = query_owner(deps.as_ref(), env.clone()).unwrap();
+        assert_eq!(res.owner, Some(Addr::unchecked("new_owner")));
+        assert_eq!(res.pending_owner, None);
+
+        // the previous owner lost the owner rights, along with the shops it owned
+        let msg = ExecuteMsg::SetOrderTimeout { seconds: 60 };
+        let err = execute(deps.as_mut(), env.clone(), owner.clone(), msg.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), env.clone(), new_owner.clone(), msg.clone()).unwrap();
+
+        let grant = ExecuteMsg::GrantRole {
+            coffee_shop_key: "shop".to_string(),
+            addr: Addr::unchecked("barista"),
+            role: Role::Barista,
+        };
+        let err = execute(deps.as_mut(), env.clone(), owner, grant.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), env.clone(), new_owner.clone(), grant.clone()).unwrap();
+        let shop = query_shop_info(deps.as_ref(), env.clone(), "shop".to_string()).unwrap();
+        assert_eq!(shop.owner, Addr::unchecked("new_owner"));
+
+        // renouncing the contract leaves the shops with their owner
+        let msg_renounce = ExecuteMsg::RenounceOwnership {};
+        execute(deps.as_mut(), env.clone(), new_owner.clone(), msg_renounce).unwrap();
+        assert_eq!(query_owner(deps.as_ref(), env.clone()).unwrap().owner, None);
+        let err = execute(deps.as_mut(), env.clone(), new_owner.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), env.clone(), new_owner, grant).unwrap();
+        let shop = query_shop_info(deps.as_ref(), env, "shop".to_string()).unwrap();
+        assert_eq!(shop.owner, Addr::unchecked("new_owner"));
+    }
+
+    #[test]
+    fn set_price_test() {
+        let mut deps = mock_dependencies(&[]);
+        let creator = String::from("creator");
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("addr"),
+            shop_key: shop_key.clone(),
+        };
+        let info = mock_info(&creator, &[]);
+
+        let res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        let zero_value = Uint128::zero();
+        let id = Uint128::new(1);
+        let msg_zeros = ExecuteMsg::SetPrice {
+            coffee_shop_key: shop_key.clone(),
+            id: zero_value,
+            size: None,
+            price: zero_value,
+        };
+        let msg = ExecuteMsg::SetPrice {
+            coffee_shop_key: shop_key.clone(),
+            id,
+            size: None,
+            price: id,
+        };
+
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        let menu = query_menu(deps.as_ref(), shop_key.clone()).unwrap().menu;
+
+        assert_ne!(menu[zero_value.u128() as usize].item.cup.sizes[0].price, zero_value);
+        assert_eq!(menu[id.u128() as usize - 1].item.cup.sizes[0].price, id);
+
+        let res = execute(deps.as_mut(), mock_env(), info, msg_zeros.clone()).unwrap_err();
+        assert_eq!(res, ContractError::MenuItemNotFound { id: zero_value });
+    }
+
+    #[test]
+    fn receive_rejects_foreign_token() {
+        let mut deps = mock_dependencies(&[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("customer"),
+            amount: Uint128::new(1000),
+            msg: to_binary(&ReceiveMsg::BuyCoffee {
+                coffee_shop_key: shop_key,
+                id: Uint128::new(1),
+                size: None,
+                modifiers: vec![],
+                amount: Uint128::new(1),
+                expected_price: None,
+                max_total: None,
+            })
+            .unwrap(),
+        });
+        let res = execute(deps.as_mut(), mock_env(), mock_info("other_token", &[]), msg)
+            .unwrap_err();
+        assert_eq!(res, ContractError::InvalidToken {});
+    }
+
+    #[test]
+    fn buy_coffee_with_native_coins() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(1000));
+
+        let buy_msg = ExecuteMsg::BuyCoffee {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(1),
+            size: None,
+            modifiers: vec![],
+            amount: Uint128::new(2),
+            expected_price: None,
+            max_total: None,
+        };
+
+        // items without a native price only accept the coffee token
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(100, "uluna")),
+            buy_msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::NativePaymentNotAccepted {});
+
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: shop_key,
+            id: Uint128::new(1),
+            size: None,
+            price: Some(Coin::new(30, "uluna")),
+        };
+        execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(100, "uusd")),
+            buy_msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::InvalidToken {});
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(50, "uluna")),
+            buy_msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::NotEnoughFunds {});
+
+        // change is sent back to the customer
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(100, "uluna")),
+            buy_msg,
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("customer"),
+                amount: coins(40, "uluna"),
+            })
+        );
+    }
+
+    #[test]
+    fn transfer_native_tokens_to_owner_test() {
+        let mut deps = mock_dependencies(&coins(500, "uluna"));
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: "shop".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::TransferAllNativeTokens {
+            coffee_shop_key: None,
+            denom: String::from("uluna"),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("creator"),
+                amount: coins(500, "uluna"),
+            })
+        );
+    }
+
+    #[test]
+    fn update_config_test() {
+        let mut deps = mock_dependencies(&coins(500, "uluna"));
+        let shop_key = "shop".to_string();
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let config = query_config(deps.as_ref(), mock_env()).unwrap();
+        assert_eq!(config.default_cup_weight, Uint128::new(AVERAGE_CUP_WEIGHT));
+        assert_eq!(config.fee, None);
+
+        let update = |token_addr: Option<&str>, weight: Option<u128>, fee: Option<FeeConfig>| {
+            ExecuteMsg::UpdateConfig {
+                token_addr: token_addr.map(Addr::unchecked),
+                default_cup_weight: weight.map(Uint128::new),
+                fee,
+            }
+        };
+        let fee = |rate: u64| FeeConfig {
+            collector: Addr::unchecked("collector"),
+            rate: Decimal::percent(rate),
+        };
+
+        let msg = update(Some("new_token"), None, None);
+        let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), msg).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let msg = update(None, Some(0), None);
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+        assert_eq!(res, ContractError::InvalidParam {});
+        // a fee of the whole sale would leave the shop nothing
+        for rate in [100, 101].iter() {
+            let msg = update(None, None, Some(fee(*rate)));
+            let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg);
+            assert_eq!(
+                res.unwrap_err(),
+                ContractError::InvalidFeeRate {
+                    rate: Decimal::percent(*rate)
+                }
+            );
+        }
+
+        let msg = update(Some("new_token"), Some(300), Some(fee(10)));
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let config = query_config(deps.as_ref(), mock_env()).unwrap();
+        assert_eq!(config.coffee_token_addr, Addr::unchecked("new_token"));
+        assert_eq!(config.previous_token_addrs, vec![Addr::unchecked("coffee_token")]);
+        assert_eq!(config.default_cup_weight, Uint128::new(300));
+        assert_eq!(config.fee, Some(fee(10)));
+
+        // a replaced token is no longer accepted as payment
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("customer"),
+            amount: Uint128::new(1000),
+            msg: to_binary(&ReceiveMsg::BuyCoffee {
+                coffee_shop_key: shop_key.clone(),
+                id: Uint128::new(1),
+                size: None,
+                modifiers: vec![],
+                amount: Uint128::new(1),
+                expected_price: None,
+                max_total: None,
+            })
+            .unwrap(),
+        });
+        let res = execute(deps.as_mut(), mock_env(), mock_info("coffee_token", &[]), msg)
+            .unwrap_err();
+        assert_eq!(res, ContractError::InvalidToken {});
+
+        // only the payment tokens can be withdrawn
+        let msg = ExecuteMsg::TransferAllTokens {
+            coffee_shop_key: None,
+            token: Some(Addr::unchecked("other_token")),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+        assert_eq!(res, ContractError::InvalidToken {});
+
+        // sizes without a weight take the default
+        let mut sizes = default_sizes();
+        sizes[0].weight = Uint128::zero();
+        let msg = ExecuteMsg::AddMenuItem {
+            coffee_shop_key: shop_key.clone(),
+            cup: CoffeeCup {
+                name: String::from("Espresso"),
+                sizes,
+            },
+            recipe: CoffeeRecipe {
+                ingredients: vec![IngredientCupShare {
+                    ingredient_type: String::from(BEANS),
+                    share: Uint128::new(100),
+                }],
+            },
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let menu = query_menu(deps.as_ref(), shop_key).unwrap().menu;
+        assert_eq!(menu[3].item.cup.sizes[0].weight, Uint128::new(300));
+
+        // the fee is split off the withdrawal
+        let msg = ExecuteMsg::TransferAllNativeTokens {
+            coffee_shop_key: None,
+            denom: String::from("uluna"),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages.iter().map(|msg| msg.msg.clone()).collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("creator"),
+                    amount: coins(450, "uluna"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("collector"),
+                    amount: coins(50, "uluna"),
+                }),
+            ]
+        );
+
+        // a zero rate turns the fee off
+        let msg = update(None, None, Some(fee(0)));
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(query_config(deps.as_ref(), mock_env()).unwrap().fee, None);
+    }
+
+    #[test]
+    fn revenue_by_token_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(1000));
+
+        let buy = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("customer"),
+            amount: DEFAULT_PRICE,
+            msg: to_binary(&ReceiveMsg::BuyCoffee {
+                coffee_shop_key: shop_key.clone(),
+                id: Uint128::new(1),
+                size: None,
+                modifiers: vec![],
+                amount: Uint128::new(1),
+                expected_price: None,
+                max_total: None,
+            })
+            .unwrap(),
+        });
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("coffee_token", &[]),
+            buy.clone(),
+        )
+        .unwrap();
+        fulfill_order(deps.as_mut(), owner.clone(), Uint128::new(1));
+
+        let msg = ExecuteMsg::UpdateConfig {
+            token_addr: Some(Addr::unchecked("new_token")),
+            default_cup_weight: None,
+            fee: None,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("new_token", &[]), buy).unwrap();
+        fulfill_order(deps.as_mut(), owner.clone(), Uint128::new(2));
+
+        // sales in the replaced token aren't added to the ones in the new token
+        let info = query_shop_info(deps.as_ref(), mock_env(), shop_key.clone()).unwrap();
+        assert_eq!(
+            info.revenue,
+            vec![
+                Asset::token(Addr::unchecked("coffee_token"), DEFAULT_PRICE),
+                Asset::token(Addr::unchecked("new_token"), DEFAULT_PRICE),
+            ]
+        );
+
+        let msg = ExecuteMsg::RefundOrder {
+            order_id: Uint128::new(2),
+            restore_ingredients: false,
+            reason: None,
+        };
+        execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
+        let info = query_shop_info(deps.as_ref(), mock_env(), shop_key).unwrap();
+        assert_eq!(
+            info.revenue,
+            vec![
+                Asset::token(Addr::unchecked("coffee_token"), DEFAULT_PRICE),
+                Asset::token(Addr::unchecked("new_token"), Uint128::zero()),
+            ]
+        );
+    }
+
+    #[test]
+    fn withdraw_test() {
+        let mut deps = mock_dependencies(&coins(1000, "uluna"));
+        let owner = mock_info("creator", &[]);
+        let treasurer = mock_info("treasurer", &[]);
+        let shop_key = "shop".to_string();
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        credit_ledger(
+            deps.as_mut().storage,
+            &shop_key,
+            &Asset::native(Coin::new(1000, "uluna")),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::GrantRole {
+            coffee_shop_key: shop_key.clone(),
+            addr: Addr::unchecked("treasurer"),
+            role: Role::Treasurer,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let withdraw = |token: AssetInfo, amount: u128| ExecuteMsg::Withdraw {
+            coffee_shop_key: shop_key.clone(),
+            token,
+            amount: Uint128::new(amount),
+            recipient: Addr::unchecked("supplier"),
+        };
+        let uluna = || AssetInfo::NativeToken {
+            denom: String::from("uluna"),
+        };
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("barista", &[]),
+            withdraw(uluna(), 100),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            treasurer.clone(),
+            withdraw(uluna(), 0),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::InvalidParam {});
+        let other_token = AssetInfo::Token {
+            contract_addr: Addr::unchecked("other_token"),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            treasurer.clone(),
+            withdraw(other_token, 100),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::InvalidToken {});
+        // no more than the ledger of the shop
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            treasurer.clone(),
+            withdraw(uluna(), 1500),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::NotEnoughFunds {});
+
+        let res = execute(deps.as_mut(), mock_env(), treasurer, withdraw(uluna(), 400)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("supplier"),
+                amount: coins(400, "uluna"),
+            })
+        );
+
+        // the fee is taken out of the amount
+        let msg = ExecuteMsg::UpdateConfig {
+            token_addr: None,
+            default_cup_weight: None,
+            fee: Some(FeeConfig {
+                collector: Addr::unchecked("collector"),
+                rate: Decimal::percent(10),
+            }),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), owner, withdraw(uluna(), 600)).unwrap();
+        assert_eq!(
+            res.messages
+                .iter()
+                .map(|msg| msg.msg.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("supplier"),
+                    amount: coins(540, "uluna"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("collector"),
+                    amount: coins(60, "uluna"),
+                }),
+            ]
+        );
+        assert!(query_ledger(deps.as_ref(), shop_key.clone())
+            .unwrap()
+            .balances
+            .is_empty());
+
+        let history = query_withdrawals(deps.as_ref(), shop_key.clone(), None, None)
+            .unwrap()
+            .withdrawals;
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            history[1],
+            Withdrawal {
+                id: Uint128::new(2),
+                shop_key: shop_key.clone(),
+                asset: Asset::native(Coin::new(600, "uluna")),
+                fee: Uint128::new(60),
+                recipient: Addr::unchecked("supplier"),
+                withdrawn_by: Addr::unchecked("creator"),
+                time: mock_env().block.time,
+            }
+        );
+        let history = query_withdrawals(deps.as_ref(), shop_key, Some(Uint128::new(1)), None)
+            .unwrap()
+            .withdrawals;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].withdrawn_by, Addr::unchecked("creator"));
+
+        // the contract owner has no say over the revenue of a shop it doesn't own
+        let msg = ExecuteMsg::CreateShop {
+            shop_key: "downtown".to_string(),
+            owner: Addr::unchecked("shop_owner"),
+            menu: vec![],
+            recipes: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        credit_ledger(
+            deps.as_mut().storage,
+            "downtown",
+            &Asset::native(Coin::new(100, "uluna")),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::Withdraw {
+            coffee_shop_key: "downtown".to_string(),
+            token: uluna(),
+            amount: Uint128::new(100),
+            recipient: Addr::unchecked("creator"),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), mock_info("shop_owner", &[]), msg).unwrap();
+    }
+
+    #[test]
+    fn create_shop_test() {
+        let mut deps = mock_dependencies(&[]);
+        let creator = mock_info("creator", &[]);
+        let shop_owner = mock_info("shop_owner", &[]);
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: "shop".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), creator.clone(), msg).unwrap();
+
+        let create_msg = ExecuteMsg::CreateShop {
+            shop_key: "downtown".to_string(),
+            owner: Addr::unchecked("shop_owner"),
+            menu: vec![CoffeeCup {
+                name: String::from(AMERICANO),
+                sizes: default_sizes(),
+            }],
+            recipes: vec![CoffeeRecipe {
+                ingredients: vec![IngredientCupShare {
+                    ingredient_type: String::from(WATER),
+                    share: Uint128::new(100),
+                }],
+            }],
+        };
+
+        // only the contract owner opens new shops
+        let res = execute(deps.as_mut(), mock_env(), shop_owner.clone(), create_msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        execute(deps.as_mut(), mock_env(), creator.clone(), create_msg.clone()).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), creator.clone(), create_msg).unwrap_err();
+        assert_eq!(res, ContractError::ShopAlreadyExists {});
+
+        let set_price_msg = ExecuteMsg::SetPrice {
+            coffee_shop_key: "downtown".to_string(),
+            id: Uint128::new(1),
+            size: None,
+            price: Uint128::new(5),
+        };
+
+        // the new shop is administered by its own owner
+        let res = execute(deps.as_mut(), mock_env(), creator, set_price_msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), shop_owner.clone(), set_price_msg).unwrap();
+
+        let load_msg = ExecuteMsg::LoadIngredients {
+            coffee_shop_key: "downtown".to_string(),
+            portions: vec![IngredientPortion {
+                ingredient: String::from(WATER),
+                weight: Uint128::new(100),
+            }],
+        };
+        let manager = mock_info("manager", &[]);
+        let res = execute(deps.as_mut(), mock_env(), manager.clone(), load_msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        let msg = ExecuteMsg::GrantRole {
+            coffee_shop_key: "downtown".to_string(),
+            addr: Addr::unchecked("manager"),
+            role: Role::InventoryClerk,
+        };
+        execute(deps.as_mut(), mock_env(), shop_owner, msg).unwrap();
+        execute(deps.as_mut(), mock_env(), manager, load_msg).unwrap();
+    }
+
+    #[test]
+    fn shop_roles_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let clerk = mock_info("clerk", &[]);
+        let barista = mock_info("barista", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let grant = |addr: &str, role: Role| ExecuteMsg::GrantRole {
+            coffee_shop_key: shop_key.clone(),
+            addr: Addr::unchecked(addr),
+            role,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), grant("clerk", Role::InventoryClerk))
+            .unwrap();
+        execute(deps.as_mut(), mock_env(), owner.clone(), grant("barista", Role::Barista))
+            .unwrap();
+
+        // only owners grant roles
+        let res = execute(deps.as_mut(), mock_env(), clerk.clone(), grant("clerk", Role::Owner))
+            .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        let roles = query_roles(deps.as_ref(), shop_key.clone(), None, None).unwrap();
+        assert_eq!(
+            roles.staff,
+            vec![
+                StaffRoles {
+                    addr: Addr::unchecked("barista"),
+                    roles: vec![Role::Barista],
+                },
+                StaffRoles {
+                    addr: Addr::unchecked("clerk"),
+                    roles: vec![Role::InventoryClerk],
+                },
+            ]
+        );
+
+        // the clerk keeps the stock but doesn't set prices
+        load_all_ingredients(deps.as_mut(), clerk.clone(), &shop_key, Uint128::new(1000));
+        let msg = ExecuteMsg::WriteOffIngredients {
+            coffee_shop_key: shop_key.clone(),
+            portions: vec![IngredientPortion {
+                ingredient: String::from(MILK),
+                weight: Uint128::new(200),
+            }],
+            reason: Some(String::from("spoiled")),
+        };
+        execute(deps.as_mut(), mock_env(), clerk.clone(), msg.clone()).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), barista.clone(), msg).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let stock = query_ingredients(deps.as_ref(), shop_key.clone())
+            .unwrap()
+            .ingredients;
+        assert!(stock.contains(&IngredientPortion {
+            ingredient: String::from(MILK),
+            weight: Uint128::new(800),
+        }));
+
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(3),
+            size: None,
+            price: Some(Coin::new(30, "uluna")),
+        };
+        let res = execute(deps.as_mut(), mock_env(), clerk.clone(), msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        // the barista moves the orders along
+        let msg = ExecuteMsg::BuyCoffee {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(3),
+            size: None,
+            modifiers: vec![],
+            amount: Uint128::new(1),
+            expected_price: None,
+            max_total: None,
+        };
+        let customer = mock_info("customer", &coins(30, "uluna"));
+        execute(deps.as_mut(), mock_env(), customer, msg).unwrap();
+
+        let msg = ExecuteMsg::StartBrewing {
+            order_id: Uint128::new(1),
+        };
+        let res = execute(deps.as_mut(), mock_env(), clerk, msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), barista.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::RevokeRole {
+            coffee_shop_key: shop_key.clone(),
+            addr: Addr::unchecked("barista"),
+            role: Role::Barista,
+        };
+        execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
+        let msg = ExecuteMsg::MarkOrderReady {
+            order_id: Uint128::new(1),
+        };
+        let res = execute(deps.as_mut(), mock_env(), barista, msg).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let roles = query_roles(deps.as_ref(), shop_key, None, None).unwrap();
+        assert_eq!(roles.staff.len(), 1);
+    }
+
+    #[test]
+    fn pause_test() {
+        let mut deps = mock_dependencies(&coins(100, "uluna"));
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(1000));
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(3),
+            size: None,
+            price: Some(Coin::new(30, "uluna")),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let buy_msg = ExecuteMsg::BuyCoffee {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(3),
+            size: None,
+            modifiers: vec![],
+            amount: Uint128::new(1),
+            expected_price: None,
+            max_total: None,
+        };
+        let customer = mock_info("customer", &coins(30, "uluna"));
+        let write_off_msg = ExecuteMsg::WriteOffIngredients {
+            coffee_shop_key: shop_key.clone(),
+            portions: vec![IngredientPortion {
+                ingredient: String::from(MILK),
+                weight: Uint128::new(100),
+            }],
+            reason: None,
+        };
+
+        // the contract owner stops every shop
+        let msg = ExecuteMsg::Pause {
+            coffee_shop_key: None,
+            reason: Some(String::from("wrong prices")),
+            expires: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), customer.clone(), msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        let config = query_config(deps.as_ref(), mock_env()).unwrap();
+        assert_eq!(
+            config.pause,
+            Some(PauseInfo {
+                reason: Some(String::from("wrong prices")),
+                expires: None,
+            })
+        );
+
+        let res = execute(deps.as_mut(), mock_env(), customer.clone(), buy_msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::ContractPaused {});
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), write_off_msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::ContractPaused {});
+
+        // withdrawals and configuration keep working
+        let msg = ExecuteMsg::TransferAllNativeTokens {
+            coffee_shop_key: None,
+            denom: String::from("uluna"),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        let msg = ExecuteMsg::SetPrice {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(3),
+            size: None,
+            price: Uint128::new(10),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Unpause {
+            coffee_shop_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        assert_eq!(query_config(deps.as_ref(), mock_env()).unwrap().pause, None);
+
+        // a shop pause lifts by itself once it expires
+        let env = mock_env();
+        let expires = env.block.time.plus_seconds(60);
+        let msg = ExecuteMsg::Pause {
+            coffee_shop_key: Some(shop_key.clone()),
+            reason: None,
+            expires: Some(expires),
+        };
+        execute(deps.as_mut(), env.clone(), owner.clone(), msg).unwrap();
+        let info = query_shop_info(deps.as_ref(), env.clone(), shop_key.clone()).unwrap();
+        assert_eq!(
+            info.pause,
+            Some(PauseInfo {
+                reason: None,
+                expires: Some(expires),
+            })
+        );
+        let res = execute(deps.as_mut(), env.clone(), customer.clone(), buy_msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::ShopPaused {});
+        let res = execute(deps.as_mut(), env.clone(), owner.clone(), write_off_msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::ShopPaused {});
+
+        let mut later = env;
+        later.block.time = expires;
+        let info = query_shop_info(deps.as_ref(), later.clone(), shop_key).unwrap();
+        assert_eq!(info.pause, None);
+        execute(deps.as_mut(), later.clone(), customer, buy_msg).unwrap();
+        execute(deps.as_mut(), later, owner, write_off_msg).unwrap();
+    }
+
+    #[test]
+    fn list_shops_test() {
+        let mut deps = mock_dependencies(&[]);
+        let creator = mock_info("creator", &[]);
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: "airport".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), creator.clone(), msg).unwrap();
+        for key in ["downtown", "campus"].iter() {
+            let msg = ExecuteMsg::CreateShop {
+                shop_key: key.to_string(),
+                owner: Addr::unchecked("shop_owner"),
+                menu: vec![],
+                recipes: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), creator.clone(), msg).unwrap();
+        }
+
+        let page = query_list_shops(deps.as_ref(), mock_env(), None, Some(2)).unwrap().shops;
+        let keys: Vec<_> = page.iter().map(|shop| shop.shop_key.as_str()).collect();
+        assert_eq!(keys, vec!["airport", "campus"]);
+        assert_eq!(page[0].item_count, 3);
+        assert_eq!(page[1].owner, Addr::unchecked("shop_owner"));
+
+        let page = query_list_shops(deps.as_ref(), mock_env(), Some("campus".to_string()), None)
+            .unwrap()
+            .shops;
+        let keys: Vec<_> = page.iter().map(|shop| shop.shop_key.as_str()).collect();
+        assert_eq!(keys, vec!["downtown"]);
+
+        // sales are accumulated per shop
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: "airport".to_string(),
+            id: Uint128::new(3),
+            size: None,
+            price: Some(Coin::new(30, "uluna")),
+        };
+        execute(deps.as_mut(), mock_env(), creator.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), creator.clone(), "airport", Uint128::new(1000));
+
+        let buy_msg = ExecuteMsg::BuyCoffee {
+            coffee_shop_key: "airport".to_string(),
+            id: Uint128::new(3),
+            size: None,
+            modifiers: vec![],
+            amount: Uint128::new(1),
+            expected_price: None,
+            max_total: None,
+        };
+        let customer = mock_info("customer", &coins(30, "uluna"));
+        execute(deps.as_mut(), mock_env(), customer.clone(), buy_msg.clone()).unwrap();
+
+        // the payment is in escrow until the order is picked up
+        let info = query_shop_info(deps.as_ref(), mock_env(), "airport".to_string()).unwrap();
+        assert!(info.native_revenue.is_empty());
+
+        fulfill_order(deps.as_mut(), creator.clone(), Uint128::new(1));
+        let info = query_shop_info(deps.as_ref(), mock_env(), "airport".to_string()).unwrap();
+        assert!(info.active);
+        assert!(info.revenue.is_empty());
+        assert_eq!(info.native_revenue, coins(30, "uluna"));
+
+        // inactive shops stop selling
+        let msg = ExecuteMsg::SetShopActive {
+            coffee_shop_key: "airport".to_string(),
+            active: false,
+        };
+        execute(deps.as_mut(), mock_env(), creator, msg).unwrap();
+        assert!(!query_shop_info(deps.as_ref(), mock_env(), "airport".to_string()).unwrap().active);
+
+        let res = execute(deps.as_mut(), mock_env(), customer, buy_msg).unwrap_err();
+        assert_eq!(res, ContractError::ShopInactive {});
+    }
+
+    #[test]
+    fn menu_management_keeps_ids() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let espresso = CoffeeCup {
+            name: String::from("Espresso"),
+            sizes: vec![CupSize {
+                name: String::from("single"),
+                weight: Uint128::new(30),
+                price: Uint128::new(700),
+                native_price: None,
+            }],
+        };
+        let recipe = CoffeeRecipe {
+            ingredients: vec![
+                IngredientCupShare {
+                    ingredient_type: String::from(WATER),
+                    share: Uint128::new(60),
+                },
+                IngredientCupShare {
+                    ingredient_type: String::from(BEANS),
+                    share: Uint128::new(40),
+                },
+            ],
+        };
+        let msg = ExecuteMsg::AddMenuItem {
+            coffee_shop_key: shop_key.clone(),
+            cup: espresso.clone(),
+            recipe: recipe.clone(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        assert_eq!(res.attributes[1], attr("id", "4"));
+
+        // removing an item doesn't shift the ids of the others
+        let msg = ExecuteMsg::RemoveMenuItem {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(2),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let ids: Vec<_> = query_menu(deps.as_ref(), shop_key.clone())
+            .unwrap()
+            .menu
+            .iter()
+            .map(|entry| entry.item.id.u128())
+            .collect();
+        assert_eq!(ids, vec![1, 3, 4]);
+        assert_eq!(
+            query_price(deps.as_ref(), shop_key.clone(), Uint128::new(4), None).unwrap(),
+            espresso.sizes[0].price
+        );
+        assert_eq!(
+            query_price(deps.as_ref(), shop_key.clone(), Uint128::new(2), None).unwrap_err(),
+            ContractError::MenuItemNotFound {
+                id: Uint128::new(2)
+            }
+        );
+
+        let msg = ExecuteMsg::ReorderMenu {
+            coffee_shop_key: shop_key.clone(),
+            ids: vec![Uint128::new(4), Uint128::new(1), Uint128::new(4)],
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+        assert_eq!(res, ContractError::InvalidParam {});
+
+        let msg = ExecuteMsg::ReorderMenu {
+            coffee_shop_key: shop_key.clone(),
+            ids: vec![Uint128::new(4), Uint128::new(1), Uint128::new(3)],
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateMenuItem {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(4),
+            cup: CoffeeCup {
+                name: String::from("Double Espresso"),
+                ..espresso
+            },
+            recipe: recipe.clone(),
+        };
+        execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
+
+        let state = COFFEE_STATE.load(deps.as_ref().storage, shop_key).unwrap();
+        assert_eq!(state.menu[0].id, Uint128::new(4));
+        assert_eq!(state.menu[0].cup.name, "Double Espresso");
+        assert_eq!(state.menu[0].recipe, recipe);
+        assert_eq!(state.menu[2].id, Uint128::new(3));
+        assert_eq!(state.menu[2].cup.name, AMERICANO);
+    }
+
+    #[test]
+    fn cup_sizes_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(1000));
+
+        let size = |name: &str, weight: u128, price: u128| CupSize {
+            name: String::from(name),
+            weight: Uint128::new(weight),
+            price: Uint128::new(price),
+            native_price: Some(Coin::new(price / 10, "uluna")),
+        };
+        let recipe = CoffeeRecipe {
+            ingredients: vec![
+                IngredientCupShare {
+                    ingredient_type: String::from(WATER),
+                    share: Uint128::new(60),
+                },
+                IngredientCupShare {
+                    ingredient_type: String::from(BEANS),
+                    share: Uint128::new(40),
+                },
+            ],
+        };
+
+        // size names must be unique within an item
+        let msg = ExecuteMsg::AddMenuItem {
+            coffee_shop_key: shop_key.clone(),
+            cup: CoffeeCup {
+                name: String::from("Filter"),
+                sizes: vec![size("small", 200, 100), size("small", 400, 200)],
+            },
+            recipe: recipe.clone(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+        assert_eq!(res, ContractError::InvalidParam {});
+
+        let msg = ExecuteMsg::AddMenuItem {
+            coffee_shop_key: shop_key.clone(),
+            cup: CoffeeCup {
+                name: String::from("Filter"),
+                sizes: vec![size("small", 200, 100), size("large", 400, 200)],
+            },
+            recipe,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        let id = Uint128::new(4);
+
+        assert_eq!(
+            query_price(deps.as_ref(), shop_key.clone(), id, None).unwrap(),
+            Uint128::new(100)
+        );
+        assert_eq!(
+            query_price(deps.as_ref(), shop_key.clone(), id, Some("large".to_string())).unwrap(),
+            Uint128::new(200)
+        );
+
+        let msg = ExecuteMsg::SetPrice {
+            coffee_shop_key: shop_key.clone(),
+            id,
+            size: Some("medium".to_string()),
+            price: Uint128::new(150),
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner, msg).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::UnknownSize {
+                size: "medium".to_string()
+            }
+        );
+
+        // the large size costs its own price and takes its own weight from the stock
+        let buy_msg = ExecuteMsg::BuyCoffee {
+            coffee_shop_key: shop_key.clone(),
+            id,
+            size: Some("large".to_string()),
+            modifiers: vec![],
+            amount: Uint128::new(1),
+            expected_price: None,
+            max_total: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(10, "uluna")),
+            buy_msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::NotEnoughFunds {});
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(20, "uluna")),
+            buy_msg,
+        )
+        .unwrap();
+
+        let stock = query_ingredients(deps.as_ref(), shop_key).unwrap().ingredients;
+        let weight_of = |id: &str| {
+            stock
+                .iter()
+                .find(|portion| portion.ingredient == id)
+                .unwrap()
+                .weight
+        };
+        assert_eq!(weight_of(WATER), Uint128::new(1000 - 240));
+        assert_eq!(weight_of(BEANS), Uint128::new(1000 - 160));
+        assert_eq!(weight_of(MILK), Uint128::new(1000));
+    }
+
+    #[test]
+    fn buy_coffee_with_modifiers() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(1000));
+
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(2),
+            size: None,
+            price: Some(Coin::new(30, "uluna")),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let modifiers = vec![
+            Modifier {
+                id: String::from("extra_shot"),
+                name: String::from("Extra shot"),
+                price_delta: Uint128::new(100),
+                native_price_delta: Uint128::new(10),
+                effects: vec![ModifierEffect::Add {
+                    ingredient: String::from(BEANS),
+                    share: Uint128::new(10),
+                }],
+            },
+            Modifier {
+                id: String::from("no_sugar"),
+                name: String::from("No sugar"),
+                price_delta: Uint128::zero(),
+                native_price_delta: Uint128::zero(),
+                effects: vec![ModifierEffect::Remove {
+                    ingredient: String::from(SUGAR),
+                }],
+            },
+            Modifier {
+                id: String::from("less_milk"),
+                name: String::from("Less milk"),
+                price_delta: Uint128::zero(),
+                native_price_delta: Uint128::zero(),
+                effects: vec![
+                    ModifierEffect::Remove {
+                        ingredient: String::from(MILK),
+                    },
+                    ModifierEffect::Add {
+                        ingredient: String::from(MILK),
+                        share: Uint128::new(25),
+                    },
+                ],
+            },
+        ];
+        let msg = ExecuteMsg::SetModifiers {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(2),
+            modifiers,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
+
+        let buy = |modifiers: Vec<&str>| ExecuteMsg::BuyCoffee {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(2),
+            size: None,
+            modifiers: modifiers.into_iter().map(String::from).collect(),
+            amount: Uint128::new(2),
+            expected_price: None,
+            max_total: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(100, "uluna")),
+            buy(vec!["oat_milk"]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::UnknownModifier {
+                modifier: String::from("oat_milk")
+            }
+        );
+
+        // the latte recipe already takes the whole cup
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(100, "uluna")),
+            buy(vec!["extra_shot", "no_sugar"]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::SharesExceedPrecision {
+                total: Uint128::new(110),
+                max: SHARE_PRECISION,
+            }
+        );
+
+        // two cups at 30 + 10 each
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(100, "uluna")),
+            buy(vec!["extra_shot", "less_milk", "no_sugar"]),
+        )
+        .unwrap();
+        assert_eq!(res.attributes[1], attr("change", "20"));
+
+        // the latte recipe is water 25, beans 25, milk 50, here with half the milk and
+        // an extra shot
+        let stock = query_ingredients(deps.as_ref(), shop_key).unwrap().ingredients;
+        let weight_of = |id: &str| {
+            stock
+                .iter()
+                .find(|portion| portion.ingredient == id)
+                .unwrap()
+                .weight
+        };
+        assert_eq!(weight_of(WATER), Uint128::new(1000 - 125));
+        assert_eq!(weight_of(BEANS), Uint128::new(1000 - 175));
+        assert_eq!(weight_of(MILK), Uint128::new(1000 - 125));
+        assert_eq!(weight_of(SUGAR), Uint128::new(1000));
+    }
+
+    #[test]
+    fn purchase_price_guards() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let customer = mock_info("customer", &coins(100, "uluna"));
+        let shop_key = "shop".to_string();
 
         let msg = InstantiateMsg {
-            token_addr: Addr::unchecked("addr"),
+            token_addr: Addr::unchecked("coffee_token"),
             shop_key: shop_key.clone(),
         };
-        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(1000));
 
-        let res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
-        assert_eq!(0, res.messages.len());
+        // the price goes up right before the purchase
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(1),
+            size: None,
+            price: Some(Coin::new(30, "uluna")),
+        };
+        execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
 
-        let zero_value = Uint128::zero();
-        let id = Uint128::new(1);
-        let msg_zeros = ExecuteMsg::SetPrice {
+        let buy_msg = |expected_price: Option<u128>, max_total: Option<u128>| {
+            ExecuteMsg::BuyCoffee {
+                coffee_shop_key: shop_key.clone(),
+                id: Uint128::new(1),
+                size: None,
+                modifiers: vec![],
+                amount: Uint128::new(2),
+                expected_price: expected_price.map(Uint128::new),
+                max_total: max_total.map(Uint128::new),
+            }
+        };
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            customer.clone(),
+            buy_msg(Some(25), None),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::PriceAboveExpected {
+                price: Uint128::new(30),
+                expected_price: Uint128::new(25)
+            }
+        );
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            customer.clone(),
+            buy_msg(None, Some(50)),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::TotalAboveMax {
+                total: Uint128::new(60),
+                max_total: Uint128::new(50)
+            }
+        );
+
+        execute(deps.as_mut(), mock_env(), customer, buy_msg(Some(30), Some(60))).unwrap();
+    }
+
+    #[test]
+    fn simulate_purchase_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner, &shop_key, Uint128::new(100));
+
+        let simulate = |deps: Deps, size: Option<&str>| {
+            query(
+                deps,
+                mock_env(),
+                QueryMsg::SimulatePurchase {
+                    coffee_shop_key: shop_key.clone(),
+                    item: Uint128::new(2),
+                    size: size.map(String::from),
+                    modifiers: vec![],
+                    amount: Uint128::new(1),
+                },
+            )
+            .map(|bin| from_binary::<SimulatePurchaseResponse>(&bin).unwrap())
+        };
+
+        // a latte takes half of its 250 weight in milk
+        let quote = simulate(deps.as_ref(), None).unwrap();
+        assert_eq!(quote.size, DEFAULT_SIZE);
+        assert_eq!(quote.total, DEFAULT_PRICE);
+        assert_eq!(quote.native_total, None);
+        assert_eq!(
+            quote.ingredients,
+            vec![
+                IngredientPortion {
+                    ingredient: String::from(WATER),
+                    weight: Uint128::new(62),
+                },
+                IngredientPortion {
+                    ingredient: String::from(BEANS),
+                    weight: Uint128::new(62),
+                },
+                IngredientPortion {
+                    ingredient: String::from(MILK),
+                    weight: Uint128::new(125),
+                },
+            ]
+        );
+        assert!(!quote.enough_stock);
+        assert_eq!(quote.short_ingredients, vec![String::from(MILK)]);
+        assert_eq!(
+            quote.reason,
+            Some(
+                ContractError::InsufficientIngredient {
+                    ingredient: String::from(MILK),
+                    needed: Uint128::new(125),
+                    available: Uint128::new(100),
+                }
+                .to_string()
+            )
+        );
+
+        // execution fails for the same reason
+        let msg = ExecuteMsg::BuyCoffee {
             coffee_shop_key: shop_key.clone(),
-            id: zero_value,
-            price: zero_value,
+            id: Uint128::new(2),
+            size: None,
+            modifiers: vec![],
+            amount: Uint128::new(1),
+            expected_price: None,
+            max_total: None,
         };
-        let msg = ExecuteMsg::SetPrice {
+        let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), msg)
+            .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InsufficientIngredient {
+                ingredient: String::from(MILK),
+                needed: Uint128::new(125),
+                available: Uint128::new(100),
+            }
+        );
+
+        assert_eq!(
+            simulate(deps.as_ref(), Some("large")).unwrap_err(),
+            ContractError::UnknownSize {
+                size: String::from("large")
+            }
+        );
+
+        // the shop state is reported before the stock, the pause before the shop state
+        let msg = ExecuteMsg::SetShopActive {
             coffee_shop_key: shop_key.clone(),
-            id,
-            price: id,
+            active: false,
         };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let quote = simulate(deps.as_ref(), None).unwrap();
+        assert_eq!(
+            quote.reason,
+            Some(ContractError::ShopInactive {}.to_string())
+        );
 
-        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
-        assert_eq!(0, res.messages.len());
+        let msg = ExecuteMsg::Pause {
+            coffee_shop_key: None,
+            reason: None,
+            expires: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let quote = simulate(deps.as_ref(), None).unwrap();
+        assert_eq!(
+            quote.reason,
+            Some(ContractError::ContractPaused {}.to_string())
+        );
+        assert!(!quote.enough_stock);
+    }
+
+    #[test]
+    fn availability_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(100));
 
+        // a latte takes 125 of milk
+        let availability = query_availability(deps.as_ref(), shop_key.clone()).unwrap();
+        assert_eq!(availability.items.len(), 3);
+        assert_eq!(
+            availability.items[1],
+            ItemAvailability {
+                id: Uint128::new(2),
+                name: String::from(LATE),
+                sizes: vec![SizeAvailability {
+                    size: String::from(DEFAULT_SIZE),
+                    cups: Uint128::zero(),
+                    limiting_ingredient: Some(String::from(MILK)),
+                }],
+            }
+        );
         let menu = query_menu(deps.as_ref(), shop_key.clone()).unwrap().menu;
+        assert!(menu.iter().all(|entry| !entry.available));
+
+        load_all_ingredients(deps.as_mut(), owner, &shop_key, Uint128::new(100));
+        let availability = query_availability(deps.as_ref(), shop_key.clone()).unwrap();
+        assert_eq!(availability.items[1].sizes[0].cups, Uint128::new(1));
+        let msg = QueryMsg::Menu {
+            coffee_shop_key: shop_key,
+        };
+        let menu = from_binary::<MenuResponse>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .menu;
+        assert!(menu[1].available);
+    }
 
-        assert_ne!(menu[zero_value.u128() as usize].price, zero_value);
-        assert_eq!(menu[id.u128() as usize - 1].price, id);
+    #[test]
+    fn place_order_checks_combined_stock() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
 
-        let res = execute(deps.as_mut(), mock_env(), info, msg_zeros.clone()).unwrap_err();
-        assert_eq!(res.to_string(), "InvalidParam");
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(200));
+
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(2),
+            size: None,
+            price: Some(Coin::new(30, "uluna")),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        // a latte takes 125 of milk, each line fits the stock but both together don't
+        let line = OrderLine {
+            item: Uint128::new(2),
+            size: None,
+            modifiers: vec![],
+            quantity: Uint128::new(1),
+            expected_price: None,
+        };
+        let order_msg = ExecuteMsg::PlaceOrder {
+            coffee_shop_key: shop_key.clone(),
+            lines: vec![line.clone(), line],
+            max_total: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(60, "uluna")),
+            order_msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InsufficientIngredient {
+                ingredient: String::from(MILK),
+                needed: Uint128::new(250),
+                available: Uint128::new(200),
+            }
+        );
+
+        let stock = query_ingredients(deps.as_ref(), shop_key.clone())
+            .unwrap()
+            .ingredients;
+        assert!(stock.iter().all(|portion| portion.weight == Uint128::new(200)));
+
+        let msg = ExecuteMsg::PlaceOrder {
+            coffee_shop_key: shop_key.clone(),
+            lines: vec![],
+            max_total: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(60, "uluna")),
+            msg,
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::InvalidParam {});
+
+        load_all_ingredients(deps.as_mut(), owner, &shop_key, Uint128::new(100));
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(60, "uluna")),
+            order_msg,
+        )
+        .unwrap();
+        assert_eq!(res.attributes[0], attr("method", "place_order"));
+        assert_eq!(res.attributes[3], attr("total", "60"));
+        assert!(res.attributes.contains(&attr("line_1_item", "2")));
+        assert!(res.attributes.contains(&attr("line_1_size", DEFAULT_SIZE)));
+        assert!(res.attributes.contains(&attr("line_1_total", "30")));
+
+        let stock = query_ingredients(deps.as_ref(), shop_key).unwrap().ingredients;
+        let milk = stock
+            .iter()
+            .find(|portion| portion.ingredient == MILK)
+            .unwrap();
+        assert_eq!(milk.weight, Uint128::new(300 - 250));
+    }
+
+    #[test]
+    fn cancel_and_refund_orders() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let customer = mock_info("customer", &coins(15, "uluna"));
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(1000));
+
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(1),
+            size: None,
+            price: Some(Coin::new(15, "uluna")),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let buy_msg = ExecuteMsg::BuyCoffee {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(1),
+            size: None,
+            modifiers: vec![],
+            amount: Uint128::new(1),
+            expected_price: None,
+            max_total: None,
+        };
+        for _ in 0..3 {
+            execute(deps.as_mut(), mock_env(), customer.clone(), buy_msg.clone()).unwrap();
+        }
+        let stock_of = |deps: Deps, id: &str| {
+            query_ingredients(deps, shop_key.clone())
+                .unwrap()
+                .ingredients
+                .into_iter()
+                .find(|portion| portion.ingredient == id)
+                .unwrap()
+                .weight
+        };
+        // a cappuccino takes 62 of beans
+        assert_eq!(stock_of(deps.as_ref(), BEANS), Uint128::new(1000 - 3 * 62));
+        let refund = BankMsg::Send {
+            to_address: customer.sender.to_string(),
+            amount: coins(15, "uluna"),
+        };
+
+        // the customer cancels before brewing and gets everything back
+        let cancel_msg = ExecuteMsg::CancelOrder {
+            order_id: Uint128::new(1),
+            reason: Some("changed my mind".to_string()),
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), cancel_msg.clone())
+            .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let res = execute(deps.as_mut(), mock_env(), customer.clone(), cancel_msg.clone())
+            .unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(refund.clone()));
+        assert_eq!(stock_of(deps.as_ref(), BEANS), Uint128::new(1000 - 2 * 62));
+        let order = query_order(deps.as_ref(), Uint128::new(1)).unwrap();
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert_eq!(order.reason, Some("changed my mind".to_string()));
+
+        let res = execute(deps.as_mut(), mock_env(), customer.clone(), cancel_msg).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidOrderStatus {
+                order_id: Uint128::new(1),
+                status: "cancelled".to_string()
+            }
+        );
+
+        // brewing orders can only be refunded by the staff
+        let msg = ExecuteMsg::StartBrewing {
+            order_id: Uint128::new(2),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        let msg = ExecuteMsg::CancelOrder {
+            order_id: Uint128::new(2),
+            reason: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), customer.clone(), msg).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidOrderStatus {
+                order_id: Uint128::new(2),
+                status: "brewing".to_string()
+            }
+        );
+        let msg = ExecuteMsg::RefundOrder {
+            order_id: Uint128::new(2),
+            restore_ingredients: false,
+            reason: Some("spilled".to_string()),
+        };
+        let res = execute(deps.as_mut(), mock_env(), customer, msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(refund.clone()));
+        assert_eq!(stock_of(deps.as_ref(), BEANS), Uint128::new(1000 - 2 * 62));
+
+        // refunding a picked up order takes the payment out of the revenue
+        fulfill_order(deps.as_mut(), owner.clone(), Uint128::new(3));
+        assert_eq!(
+            query_shop_info(deps.as_ref(), mock_env(), shop_key.clone())
+                .unwrap()
+                .native_revenue,
+            coins(15, "uluna")
+        );
+        let msg = ExecuteMsg::RefundOrder {
+            order_id: Uint128::new(3),
+            restore_ingredients: true,
+            reason: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(refund));
+        assert_eq!(stock_of(deps.as_ref(), BEANS), Uint128::new(1000 - 62));
+        assert_eq!(
+            query_shop_info(deps.as_ref(), mock_env(), shop_key).unwrap().native_revenue,
+            coins(0, "uluna")
+        );
+        assert_eq!(
+            query_order(deps.as_ref(), Uint128::new(3)).unwrap().status,
+            OrderStatus::Refunded
+        );
+    }
+
+    #[test]
+    fn order_lifecycle_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let customer = mock_info("customer", &coins(30, "uluna"));
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::new(1000));
+
+        let msg = ExecuteMsg::SetNativePrice {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(1),
+            size: None,
+            price: Some(Coin::new(15, "uluna")),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let buy_msg = ExecuteMsg::BuyCoffee {
+            coffee_shop_key: shop_key.clone(),
+            id: Uint128::new(1),
+            size: None,
+            modifiers: vec![],
+            amount: Uint128::new(1),
+            expected_price: None,
+            max_total: None,
+        };
+        for _ in 0..2 {
+            execute(deps.as_mut(), mock_env(), customer.clone(), buy_msg.clone()).unwrap();
+        }
+        let first = Uint128::new(1);
+
+        let order = query_order(deps.as_ref(), first).unwrap();
+        assert_eq!(order.status, OrderStatus::Placed);
+        assert_eq!(order.customer, customer.sender);
+        assert_eq!(order.placed_at, mock_env().block.time);
+
+        let orders = query_customer_orders(deps.as_ref(), customer.sender.clone(), None, None)
+            .unwrap()
+            .orders;
+        assert_eq!(orders.len(), 2);
+        let queue = query_shop_queue(deps.as_ref(), shop_key.clone(), Some(first), None)
+            .unwrap()
+            .orders;
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].id, Uint128::new(2));
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            customer.clone(),
+            ExecuteMsg::StartBrewing { order_id: first },
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        // statuses can't be skipped
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            ExecuteMsg::MarkOrderReady { order_id: first },
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidOrderStatus {
+                order_id: first,
+                status: "placed".to_string()
+            }
+        );
+
+        fulfill_order(deps.as_mut(), owner.clone(), first);
+
+        // picked up orders leave the open views but stay queryable
+        assert_eq!(
+            query_order(deps.as_ref(), first).unwrap().status,
+            OrderStatus::PickedUp
+        );
+        let orders = query_customer_orders(deps.as_ref(), customer.sender, None, None)
+            .unwrap()
+            .orders;
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].id, Uint128::new(2));
+        let queue = query_shop_queue(deps.as_ref(), shop_key, None, None)
+            .unwrap()
+            .orders;
+        assert_eq!(queue.len(), 1);
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner,
+            ExecuteMsg::StartBrewing {
+                order_id: Uint128::new(3),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::OrderNotFound {
+                order_id: Uint128::new(3)
+            }
+        );
+    }
+
+    #[test]
+    fn register_ingredient_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let load_msg = ExecuteMsg::LoadIngredients {
+            coffee_shop_key: shop_key.clone(),
+            portions: vec![IngredientPortion {
+                ingredient: String::from("oat_milk"),
+                weight: Uint128::new(1000),
+            }],
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), load_msg.clone())
+            .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::UnknownIngredient {
+                ingredient: String::from("oat_milk")
+            }
+        );
+
+        let oat_milk = IngredientInfo {
+            id: String::from("oat_milk"),
+            name: String::from("Oat milk"),
+            unit: Unit::Millilitres,
+        };
+        let msg = ExecuteMsg::RegisterIngredient {
+            coffee_shop_key: shop_key.clone(),
+            ingredient: oat_milk.clone(),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg.clone()).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::IngredientAlreadyRegistered {
+                ingredient: String::from("oat_milk")
+            }
+        );
+
+        execute(deps.as_mut(), mock_env(), owner.clone(), load_msg).unwrap();
+
+        let msg = ExecuteMsg::AddMenuItem {
+            coffee_shop_key: shop_key.clone(),
+            cup: CoffeeCup {
+                name: String::from("Oat Latte"),
+                sizes: default_sizes(),
+            },
+            recipe: CoffeeRecipe {
+                ingredients: vec![
+                    IngredientCupShare {
+                        ingredient_type: String::from(BEANS),
+                        share: Uint128::new(25),
+                    },
+                    IngredientCupShare {
+                        ingredient_type: String::from("oat_milk"),
+                        share: Uint128::new(75),
+                    },
+                ],
+            },
+        };
+        execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
+
+        let ingredients = query_ingredients(deps.as_ref(), shop_key).unwrap();
+        assert_eq!(ingredients.catalogue.last(), Some(&oat_milk));
+        assert_eq!(
+            ingredients.ingredients.last(),
+            Some(&IngredientPortion {
+                ingredient: String::from("oat_milk"),
+                weight: Uint128::new(1000),
+            })
+        );
+    }
+
+    #[test]
+    fn failure_paths_return_errors() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = mock_info("creator", &[]);
+        let customer = mock_info("customer", &[]);
+        let shop_key = "shop".to_string();
+
+        let msg = InstantiateMsg {
+            token_addr: Addr::unchecked("coffee_token"),
+            shop_key: shop_key.clone(),
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let buy = |key: &str, id: u128, amount: Uint128| ExecuteMsg::BuyCoffee {
+            coffee_shop_key: key.to_string(),
+            id: Uint128::new(id),
+            size: None,
+            modifiers: vec![],
+            amount,
+            expected_price: None,
+            max_total: None,
+        };
+
+        // unknown shop keys, both in the update closures and in the purchase
+        let msg = ExecuteMsg::SetPrice {
+            coffee_shop_key: String::from("unknown"),
+            id: Uint128::new(1),
+            size: None,
+            price: Uint128::new(10),
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ShopNotFound {
+                key: String::from("unknown")
+            }
+        );
+        let msg = buy("unknown", 1, Uint128::new(1));
+        let err = execute(deps.as_mut(), mock_env(), customer.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ShopNotFound {
+                key: String::from("unknown")
+            }
+        );
+        let unknown = || String::from("unknown");
+        let queries = [
+            QueryMsg::ShopInfo {
+                coffee_shop_key: unknown(),
+            },
+            QueryMsg::Price {
+                coffee_shop_key: unknown(),
+                id: Uint128::new(1),
+                size: None,
+            },
+            QueryMsg::Menu {
+                coffee_shop_key: unknown(),
+            },
+            QueryMsg::Recipes {
+                coffee_shop_key: unknown(),
+            },
+            QueryMsg::Ingredients {
+                coffee_shop_key: unknown(),
+            },
+            QueryMsg::Availability {
+                coffee_shop_key: unknown(),
+            },
+            QueryMsg::SimulatePurchase {
+                coffee_shop_key: unknown(),
+                item: Uint128::new(1),
+                size: None,
+                modifiers: vec![],
+                amount: Uint128::new(1),
+            },
+        ];
+        for msg in queries.iter() {
+            let err = query(deps.as_ref(), mock_env(), msg.clone()).unwrap_err();
+            assert_eq!(err, ContractError::ShopNotFound { key: unknown() });
+        }
+
+        // item ids start at 1
+        let msg = buy(&shop_key, 0, Uint128::new(1));
+        let err = execute(deps.as_mut(), mock_env(), customer.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::MenuItemNotFound { id: Uint128::zero() });
+        assert_eq!(
+            query_price(deps.as_ref(), shop_key.clone(), Uint128::zero(), None).unwrap_err(),
+            ContractError::MenuItemNotFound {
+                id: Uint128::zero()
+            }
+        );
+        assert_eq!(
+            query_price(
+                deps.as_ref(),
+                shop_key.clone(),
+                Uint128::new(1),
+                Some(unknown())
+            )
+            .unwrap_err(),
+            ContractError::UnknownSize { size: unknown() }
+        );
+
+        // a cappuccino takes 112 of water and the stock is empty
+        let msg = buy(&shop_key, 1, Uint128::new(1));
+        let err = execute(deps.as_mut(), mock_env(), customer.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientIngredient {
+                ingredient: String::from(WATER),
+                needed: Uint128::new(112),
+                available: Uint128::zero(),
+            }
+        );
+
+        let msg = buy(&shop_key, 1, Uint128::MAX);
+        let err = execute(deps.as_mut(), mock_env(), customer, msg).unwrap_err();
+        assert_eq!(err, ContractError::Overflow {});
+
+        load_all_ingredients(deps.as_mut(), owner.clone(), &shop_key, Uint128::MAX);
+        let msg = ExecuteMsg::LoadIngredients {
+            coffee_shop_key: shop_key,
+            portions: vec![IngredientPortion {
+                ingredient: String::from(WATER),
+                weight: Uint128::new(1),
+            }],
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner, msg).unwrap_err();
+        assert_eq!(err, ContractError::Overflow {});
     }
 }
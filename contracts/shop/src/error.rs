@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Decimal, OverflowError, StdError, Timestamp, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -10,16 +10,83 @@ pub enum ContractError {
     Unauthorized {},
     #[error("InvalidParam")]
     InvalidParam {},
-    #[error("NotEnoughIngredients")]
-    NotEnoughIngredients {},
     #[error("InternalError")]
     InternalError {},
+    #[error("InvalidToken")]
+    InvalidToken {},
+    #[error("NativePaymentNotAccepted")]
+    NativePaymentNotAccepted {},
+    #[error("ShopAlreadyExists")]
+    ShopAlreadyExists {},
+    #[error("ShopInactive")]
+    ShopInactive {},
+    #[error("ContractPaused")]
+    ContractPaused {},
+    #[error("ShopPaused")]
+    ShopPaused {},
+    #[error("DuplicateIngredient: {ingredient} is listed more than once")]
+    DuplicateIngredient { ingredient: String },
+    #[error("ZeroShare: {ingredient} has a zero share in the recipe")]
+    ZeroShare { ingredient: String },
+    #[error("SharesExceedPrecision: recipe shares add up to {total}, max is {max}")]
+    SharesExceedPrecision { total: Uint128, max: Uint128 },
+    #[error("UnknownIngredient: {ingredient} isn't registered by the shop")]
+    UnknownIngredient { ingredient: String },
+    #[error("UnknownSize: the item has no size {size}")]
+    UnknownSize { size: String },
+    #[error("UnknownModifier: the item has no modifier {modifier}")]
+    UnknownModifier { modifier: String },
+    #[error("OrderNotFound: {order_id}")]
+    OrderNotFound { order_id: Uint128 },
+    #[error("InvalidOrderStatus: order {order_id} is {status}")]
+    InvalidOrderStatus { order_id: Uint128, status: String },
+    #[error("OrderNotExpired: order {order_id} can be reclaimed from {expires_at}")]
+    OrderNotExpired { order_id: Uint128, expires_at: Timestamp },
+    #[error("PriceAboveExpected: the price is {price}, expected at most {expected_price}")]
+    PriceAboveExpected { price: Uint128, expected_price: Uint128 },
+    #[error("TotalAboveMax: the total is {total}, the maximum is {max_total}")]
+    TotalAboveMax { total: Uint128, max_total: Uint128 },
+    #[error("ShopNotFound: {key}")]
+    ShopNotFound { key: String },
+    #[error("MenuItemNotFound: {id}")]
+    MenuItemNotFound { id: Uint128 },
+    #[error("InsufficientIngredient: {needed} of {ingredient} needed, {available} available")]
+    InsufficientIngredient {
+        ingredient: String,
+        needed: Uint128,
+        available: Uint128,
+    },
+    #[error("InvalidFeeRate: the fee rate is {rate}, it has to be below 1")]
+    InvalidFeeRate { rate: Decimal },
+    #[error("Overflow")]
+    Overflow {},
+    #[error("DivideByZero")]
+    DivideByZero {},
+    #[error("NoOwnershipProposal")]
+    NoOwnershipProposal {},
+    #[error("OwnershipProposalExpired")]
+    OwnershipProposalExpired {},
+    #[error("WrongContract: can't migrate from {name}")]
+    WrongContract { name: String },
+    #[error("InvalidVersion: {version}")]
+    InvalidVersion { version: String },
+    #[error("CannotDowngrade: stored version {from} is newer than {to}")]
+    CannotDowngrade { from: String, to: String },
+    #[error("IngredientAlreadyRegistered: {ingredient}")]
+    IngredientAlreadyRegistered { ingredient: String },
     // not implemented or not used errors
     #[error("NotEnoughFunds")]
     NotEnoughFunds {},
-    #[error("NotEnoughFunds")]
+    #[error("NoAllowance")]
     NoAllowance {},
     // for internal usage only !
     #[error("NotAnError")]
     NotAnError {},
-}
\ No newline at end of file
+}
+
+// arithmetic on prices, quantities and weights reports a typed error rather than a std one
+impl From<OverflowError> for ContractError {
+    fn from(_: OverflowError) -> Self {
+        ContractError::Overflow {}
+    }
+}
@@ -2,29 +2,45 @@
 mod tests {
     use std::ops::Mul;
 
+    use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
     use cosmwasm_std::{
-        attr, to_binary, Addr, Empty, QueryRequest, Uint128,
-        WasmMsg, WasmQuery,
+        attr, to_binary, Addr, Binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, QueryRequest,
+        Response, StdError, StdResult, Uint128, WasmQuery,
     };
+    use cw2::set_contract_version;
     use cw20::{BalanceResponse, MinterResponse};
     use cw20_base::msg::{ExecuteMsg as Cw20ExecuteMsg, QueryMsg as Cw20QueryMsg};
+    use cw_storage_plus::Item;
+    use serde::{Deserialize, Serialize};
 
-    use cw20_base::state::{MinterData, TokenInfo, TOKEN_INFO};
-    use cw_multi_test::{App, Contract, ContractWrapper, Executor};
+    use cw_multi_test::{App, BankKeeper, Contract, ContractWrapper, Executor};
 
-    use crate::contract::{execute, instantiate, query};
-    use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+    use crate::asset::Asset;
+    use crate::contract::{execute, instantiate, migrate, query};
+    use crate::migration::{
+        LegacyCoffeeCup, LegacyCoffeeRecipe, LegacyCoffeeState, LegacyIngredient,
+        LegacyIngredientCupShare, LegacyIngredientPortion, LEGACY_COFFEE_STATE,
+    };
+    use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ReceiveMsg};
     use crate::products::{
-        Ingredient, IngredientPortion, IngredientsResponse,
+        CoffeeCup, CoffeeRecipe, ConfigResponse, CupSize, IngredientCupShare, IngredientPortion,
+        IngredientsResponse, LedgerResponse, MenuResponse, OrderLine, ReconcileResponse,
+        WithdrawalsResponse,
     };
+    use crate::state::FeeConfig;
 
     const ALICE: &str = "Alice";
     fn mock_app() -> App {
-        App::default()
+        App::new(
+            MockApi::default(),
+            mock_env().block,
+            BankKeeper::new(),
+            MockStorage::new(),
+        )
     }
 
     pub fn contract_coffee_swap() -> Box<dyn Contract<Empty>> {
-        let contract = ContractWrapper::new(execute, instantiate, query);
+        let contract = ContractWrapper::new(execute, instantiate, query).with_migrate(migrate);
         Box::new(contract)
     }
 
@@ -37,6 +53,65 @@ mod tests {
         Box::new(contract)
     }
 
+    fn instantiate_token(router: &mut App, owner: Addr) -> Addr {
+        let cw20_token_id = router.store_code(contract_cw20_token());
+
+        let cw20_instantiate_msg = cw20_base::msg::InstantiateMsg {
+            name: "Token".parse().unwrap(),
+            symbol: "TKN".parse().unwrap(),
+            decimals: 6,
+            initial_balances: vec![],
+            mint: Some(MinterResponse {
+                minter: owner.to_string(),
+                cap: None,
+            }),
+            marketing: None,
+        };
+        router
+            .instantiate_contract(
+                cw20_token_id,
+                owner,
+                &cw20_instantiate_msg,
+                &[],
+                "Token",
+                None,
+            )
+            .unwrap()
+    }
+
+    fn instantiate_shop(router: &mut App, owner: Addr, token_addr: Addr, shop_key: String) -> Addr {
+        let coffee_swap_id = router.store_code(contract_coffee_swap());
+
+        let msg = InstantiateMsg {
+            token_addr,
+            shop_key,
+        };
+        router
+            .instantiate_contract(coffee_swap_id, owner, &msg, &[], "Token", None)
+            .unwrap()
+    }
+
+    fn default_portions(weight: Uint128) -> Vec<IngredientPortion> {
+        vec![
+            IngredientPortion {
+                ingredient: String::from("beans"),
+                weight,
+            },
+            IngredientPortion {
+                ingredient: String::from("water"),
+                weight,
+            },
+            IngredientPortion {
+                ingredient: String::from("milk"),
+                weight,
+            },
+            IngredientPortion {
+                ingredient: String::from("sugar"),
+                weight,
+            },
+        ]
+    }
+
     fn allowance_token(router: &mut App, owner: Addr, spender: Addr, token: Addr, amount: Uint128) {
         let msg = cw20::Cw20ExecuteMsg::IncreaseAllowance {
             spender: spender.to_string(),
@@ -97,6 +172,20 @@ mod tests {
         assert_eq!(balance.balance, expected_amount);
     }
 
+    fn fulfill_order(router: &mut App, staff: Addr, contract: Addr, order_id: Uint128) {
+        for msg in [
+            ExecuteMsg::StartBrewing { order_id },
+            ExecuteMsg::MarkOrderReady { order_id },
+            ExecuteMsg::MarkOrderPickedUp { order_id },
+        ]
+        .iter()
+        {
+            router
+                .execute_contract(staff.clone(), contract.clone(), msg, &[])
+                .unwrap();
+        }
+    }
+
     fn check_and_set_price_test(
         router: &mut App,
         sender: Addr,
@@ -108,19 +197,23 @@ mod tests {
         let set_price_msg = ExecuteMsg::SetPrice {
             coffee_shop_key: shop_key.clone(),
             id,
+            size: None,
             price,
         };
 
-        let res = router.execute_contract(sender.clone(), contract.clone(), &set_price_msg, &[]);
+        router
+            .execute_contract(sender.clone(), contract.clone(), &set_price_msg, &[])
+            .unwrap();
 
         // compare set price
         let price_query = QueryMsg::Price {
             coffee_shop_key: shop_key.clone(),
             id,
+            size: None,
         };
         let res: Uint128 = router
             .wrap()
-            .query_wasm_smart(&contract.clone(), &price_query)
+            .query_wasm_smart(contract.clone(), &price_query)
             .unwrap();
 
         assert_eq!(res, price);
@@ -133,29 +226,7 @@ mod tests {
         let owner = Addr::unchecked("owner");
 
         // setup cw20 token, coffee-swap
-        let cw20_token_id = router.store_code(contract_cw20_token());
-
-        let cw20_instantiate_msg = cw20_base::msg::InstantiateMsg {
-            name: "Token".parse().unwrap(),
-            symbol: "TKN".parse().unwrap(),
-            decimals: 6,
-            initial_balances: vec![],
-            mint: Some(MinterResponse {
-                minter: owner.to_string(),
-                cap: None,
-            }),
-            marketing: None,
-        };
-        let token_addr = router
-            .instantiate_contract(
-                cw20_token_id,
-                owner.clone(),
-                &cw20_instantiate_msg,
-                &[],
-                "Token",
-                None,
-            )
-            .unwrap();
+        let token_addr = instantiate_token(&mut router, owner.clone());
 
         let alice_address = Addr::unchecked(ALICE);
         let amount = Uint128::from(u128::pow(10, 6));
@@ -171,17 +242,15 @@ mod tests {
 
         let price = Uint128::new(91);
         let allowed_spend_amount = amount.mul(amount);
-        let coffee_swap_id = router.store_code(contract_coffee_swap());
         let shop_key = "astro".to_string();
         let coffee_cup_id = Uint128::new(1);
 
-        let msg = InstantiateMsg {
-            token_addr: token_addr.clone(),
-            shop_key: shop_key.clone(),
-        };
-        let coffee_swap_addr = router
-            .instantiate_contract(coffee_swap_id, owner.clone(), &msg, &[], "Token", None)
-            .unwrap();
+        let coffee_swap_addr = instantiate_shop(
+            &mut router,
+            owner.clone(),
+            token_addr.clone(),
+            shop_key.clone(),
+        );
 
         check_and_set_price_test(
             &mut router,
@@ -192,24 +261,7 @@ mod tests {
             price,
         );
 
-        let portions = vec![
-            IngredientPortion {
-                ingredient: Ingredient::Beans,
-                weight: Uint128::new(1000),
-            },
-            IngredientPortion {
-                ingredient: Ingredient::Water,
-                weight: Uint128::new(1000),
-            },
-            IngredientPortion {
-                ingredient: Ingredient::Milk,
-                weight: Uint128::new(1000),
-            },
-            IngredientPortion {
-                ingredient: Ingredient::Sugar,
-                weight: Uint128::new(1000),
-            },
-        ];
+        let portions = default_portions(Uint128::new(1000));
 
         let load_msg = ExecuteMsg::LoadIngredients {
             coffee_shop_key: shop_key.clone(),
@@ -228,7 +280,9 @@ mod tests {
         assert_eq!(res.to_string(), "Unauthorized");
 
         // owner loads ingredients
-        let res = router.execute_contract(owner.clone(), coffee_swap_addr.clone(), &load_msg, &[]);
+        router
+            .execute_contract(owner.clone(), coffee_swap_addr.clone(), &load_msg, &[])
+            .unwrap();
 
         // check the load was successful
         let ingredients_query = QueryMsg::Ingredients {
@@ -246,8 +300,12 @@ mod tests {
 
         let buy_msg = ExecuteMsg::BuyCoffee {
             coffee_shop_key: shop_key.clone(),
-            id: coffee_cup_id.clone(),
+            id: coffee_cup_id,
+            size: None,
+            modifiers: vec![],
             amount: infinite_amount,
+            expected_price: None,
+            max_total: None,
         };
 
         let res = router
@@ -258,12 +316,16 @@ mod tests {
                 &[],
             )
             .unwrap_err();
-        assert_eq!(res.to_string(), "NotEnoughIngredients");
+        assert!(res.to_string().starts_with("InsufficientIngredient"));
 
         let buy_msg = ExecuteMsg::BuyCoffee {
             coffee_shop_key: shop_key.clone(),
-            id: coffee_cup_id.clone(),
-            amount: cup_amount.clone(),
+            id: coffee_cup_id,
+            size: None,
+            modifiers: vec![],
+            amount: cup_amount,
+            expected_price: None,
+            max_total: None,
         };
         allowance_token(
             &mut router,
@@ -274,19 +336,853 @@ mod tests {
         );
 
         // user buys coffee successfully
-        let res = router.execute_contract(
+        router
+            .execute_contract(
+                alice_address.clone(),
+                coffee_swap_addr.clone(),
+                &buy_msg,
+                &[],
+            )
+            .unwrap();
+
+        // the payment stays in escrow until the order is picked up
+        let res = router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::TransferAllTokens {
+                    coffee_shop_key: Some(shop_key.clone()),
+                    token: None,
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(res.to_string(), "NotEnoughFunds");
+
+        fulfill_order(&mut router, owner.clone(), coffee_swap_addr.clone(), Uint128::new(1));
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::TransferAllTokens {
+                    coffee_shop_key: Some(shop_key.clone()),
+                    token: None,
+                },
+                &[],
+            )
+            .unwrap();
+        check_balance(&mut router, coffee_swap_addr, token_addr, Uint128::zero());
+    }
+
+    #[test]
+    fn should_reclaim_payment_after_timeout() {
+        let mut router = mock_app();
+
+        let owner = Addr::unchecked("owner");
+        let alice_address = Addr::unchecked(ALICE);
+        let token_addr = instantiate_token(&mut router, owner.clone());
+
+        let amount = Uint128::new(10_000);
+        mint_some_token(&mut router, owner.clone(), token_addr.clone(), String::from(ALICE), amount);
+
+        let shop_key = "astro".to_string();
+        let coffee_swap_addr = instantiate_shop(
+            &mut router,
+            owner.clone(),
+            token_addr.clone(),
+            shop_key.clone(),
+        );
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::LoadIngredients {
+                    coffee_shop_key: shop_key.clone(),
+                    portions: default_portions(Uint128::new(1000)),
+                },
+                &[],
+            )
+            .unwrap();
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::SetOrderTimeout { seconds: 600 },
+                &[],
+            )
+            .unwrap();
+        allowance_token(
+            &mut router,
             alice_address.clone(),
             coffee_swap_addr.clone(),
-            &buy_msg,
-            &[],
+            token_addr.clone(),
+            amount,
         );
 
-        let res = router.execute_contract(
+        let buy_msg = ExecuteMsg::BuyCoffee {
+            coffee_shop_key: shop_key,
+            id: Uint128::new(1),
+            size: None,
+            modifiers: vec![],
+            amount: Uint128::new(1),
+            expected_price: None,
+            max_total: None,
+        };
+        router
+            .execute_contract(
+                alice_address.clone(),
+                coffee_swap_addr.clone(),
+                &buy_msg,
+                &[],
+            )
+            .unwrap();
+        let reclaim_msg = ExecuteMsg::ReclaimPayment {
+            order_id: Uint128::new(1),
+        };
+
+        // only the customer can reclaim, and only once the order has expired
+        let res = router
+            .execute_contract(owner, coffee_swap_addr.clone(), &reclaim_msg, &[])
+            .unwrap_err();
+        assert_eq!(res.to_string(), "Unauthorized");
+        router.update_block(|block| block.time = block.time.plus_seconds(599));
+        router
+            .execute_contract(
+                alice_address.clone(),
+                coffee_swap_addr.clone(),
+                &reclaim_msg,
+                &[],
+            )
+            .unwrap_err();
+
+        router.update_block(|block| block.time = block.time.plus_seconds(1));
+        router
+            .execute_contract(
+                alice_address.clone(),
+                coffee_swap_addr.clone(),
+                &reclaim_msg,
+                &[],
+            )
+            .unwrap();
+        check_balance(&mut router, alice_address.clone(), token_addr.clone(), amount);
+        check_balance(&mut router, coffee_swap_addr.clone(), token_addr, Uint128::zero());
+
+        // a reclaimed order is closed
+        let res = router
+            .execute_contract(alice_address, coffee_swap_addr, &reclaim_msg, &[])
+            .unwrap_err();
+        assert_eq!(res.to_string(), "InvalidOrderStatus: order 1 is cancelled");
+    }
+
+    #[test]
+    fn should_buy_coffee_with_cw20_send() {
+        let mut router = mock_app();
+
+        let owner = Addr::unchecked("owner");
+        let alice_address = Addr::unchecked(ALICE);
+        let token_addr = instantiate_token(&mut router, owner.clone());
+
+        let amount = Uint128::new(10_000);
+        mint_some_token(&mut router, owner.clone(), token_addr.clone(), String::from(ALICE), amount);
+
+        let shop_key = "astro".to_string();
+        let coffee_swap_addr = instantiate_shop(
+            &mut router,
+            owner.clone(),
+            token_addr.clone(),
+            shop_key.clone(),
+        );
+        let price = Uint128::new(91);
+        check_and_set_price_test(
+            &mut router,
             owner.clone(),
             coffee_swap_addr.clone(),
-            &ExecuteMsg::TransferAllTokens {},
-            &[],
+            shop_key.clone(),
+            Uint128::new(1),
+            price,
         );
-        check_balance(&mut router, coffee_swap_addr, token_addr, Uint128::zero());
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::LoadIngredients {
+                    coffee_shop_key: shop_key.clone(),
+                    portions: default_portions(Uint128::new(1000)),
+                },
+                &[],
+            )
+            .unwrap();
+
+        let cup_amount = Uint128::new(2);
+        let send_msg = |amount: Uint128| cw20::Cw20ExecuteMsg::Send {
+            contract: coffee_swap_addr.to_string(),
+            amount,
+            msg: to_binary(&ReceiveMsg::BuyCoffee {
+                coffee_shop_key: shop_key.clone(),
+                id: Uint128::new(1),
+                size: None,
+                modifiers: vec![],
+                amount: cup_amount,
+                expected_price: None,
+                max_total: None,
+            })
+            .unwrap(),
+        };
+
+        // underpayment is rejected
+        let res = router
+            .execute_contract(
+                alice_address.clone(),
+                token_addr.clone(),
+                &send_msg(price),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(res.to_string(), "NotEnoughFunds");
+
+        // overpayment is accepted and the change goes back to the customer
+        let paid = Uint128::new(500);
+        router
+            .execute_contract(
+                alice_address.clone(),
+                token_addr.clone(),
+                &send_msg(paid),
+                &[],
+            )
+            .unwrap();
+
+        let total = price.mul(cup_amount);
+        check_balance(
+            &mut router,
+            coffee_swap_addr.clone(),
+            token_addr.clone(),
+            total,
+        );
+        check_balance(
+            &mut router,
+            alice_address,
+            token_addr,
+            amount.checked_sub(total).unwrap(),
+        );
+    }
+
+    #[test]
+    fn should_place_order_with_single_transfer() {
+        let mut router = mock_app();
+
+        let owner = Addr::unchecked("owner");
+        let alice_address = Addr::unchecked(ALICE);
+        let token_addr = instantiate_token(&mut router, owner.clone());
+
+        let amount = Uint128::new(10_000);
+        mint_some_token(&mut router, owner.clone(), token_addr.clone(), String::from(ALICE), amount);
+
+        let shop_key = "astro".to_string();
+        let coffee_swap_addr = instantiate_shop(
+            &mut router,
+            owner.clone(),
+            token_addr.clone(),
+            shop_key.clone(),
+        );
+        check_and_set_price_test(
+            &mut router,
+            owner.clone(),
+            coffee_swap_addr.clone(),
+            shop_key.clone(),
+            Uint128::new(1),
+            Uint128::new(91),
+        );
+        check_and_set_price_test(
+            &mut router,
+            owner.clone(),
+            coffee_swap_addr.clone(),
+            shop_key.clone(),
+            Uint128::new(3),
+            Uint128::new(50),
+        );
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::LoadIngredients {
+                    coffee_shop_key: shop_key.clone(),
+                    portions: default_portions(Uint128::new(1000)),
+                },
+                &[],
+            )
+            .unwrap();
+        allowance_token(
+            &mut router,
+            alice_address.clone(),
+            coffee_swap_addr.clone(),
+            token_addr.clone(),
+            amount,
+        );
+
+        let order_msg = ExecuteMsg::PlaceOrder {
+            coffee_shop_key: shop_key.clone(),
+            lines: vec![
+                OrderLine {
+                    item: Uint128::new(1),
+                    size: None,
+                    modifiers: vec![],
+                    quantity: Uint128::new(2),
+                    expected_price: None,
+                },
+                OrderLine {
+                    item: Uint128::new(3),
+                    size: None,
+                    modifiers: vec![],
+                    quantity: Uint128::new(1),
+                    expected_price: None,
+                },
+            ],
+            max_total: Some(Uint128::new(91 * 2 + 50)),
+        };
+        let res = router
+            .execute_contract(
+                alice_address.clone(),
+                coffee_swap_addr.clone(),
+                &order_msg,
+                &[],
+            )
+            .unwrap();
+
+        // one TransferFrom for the whole order
+        let transfers = res
+            .events
+            .iter()
+            .filter(|event| {
+                event
+                    .attributes
+                    .contains(&attr("action", "transfer_from"))
+            })
+            .count();
+        assert_eq!(transfers, 1);
+
+        let total = Uint128::new(91 * 2 + 50);
+        check_balance(
+            &mut router,
+            coffee_swap_addr,
+            token_addr.clone(),
+            total,
+        );
+        check_balance(
+            &mut router,
+            alice_address,
+            token_addr,
+            amount.checked_sub(total).unwrap(),
+        );
+    }
+
+    #[test]
+    fn should_withdraw_replaced_token() {
+        let mut router = mock_app();
+
+        let owner = Addr::unchecked("owner");
+        let alice_address = Addr::unchecked(ALICE);
+        let collector = Addr::unchecked("collector");
+        let old_token = instantiate_token(&mut router, owner.clone());
+        let new_token = instantiate_token(&mut router, owner.clone());
+        let amount = Uint128::new(10_000);
+        mint_some_token(&mut router, owner.clone(), old_token.clone(), String::from(ALICE), amount);
+
+        let shop_key = "astro".to_string();
+        let coffee_swap_addr = instantiate_shop(
+            &mut router,
+            owner.clone(),
+            old_token.clone(),
+            shop_key.clone(),
+        );
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::LoadIngredients {
+                    coffee_shop_key: shop_key.clone(),
+                    portions: default_portions(Uint128::new(1000)),
+                },
+                &[],
+            )
+            .unwrap();
+
+        let price = Uint128::new(1000);
+        let send_msg = cw20::Cw20ExecuteMsg::Send {
+            contract: coffee_swap_addr.to_string(),
+            amount: price,
+            msg: to_binary(&ReceiveMsg::BuyCoffee {
+                coffee_shop_key: shop_key.clone(),
+                id: Uint128::new(1),
+                size: None,
+                modifiers: vec![],
+                amount: Uint128::new(1),
+                expected_price: None,
+                max_total: None,
+            })
+            .unwrap(),
+        };
+        router
+            .execute_contract(alice_address.clone(), old_token.clone(), &send_msg, &[])
+            .unwrap();
+
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::UpdateConfig {
+                    token_addr: Some(new_token.clone()),
+                    default_cup_weight: None,
+                    fee: Some(FeeConfig {
+                        collector: collector.clone(),
+                        rate: Decimal::percent(10),
+                    }),
+                },
+                &[],
+            )
+            .unwrap();
+
+        // new orders take the new token only
+        let res = router
+            .execute_contract(alice_address, old_token.clone(), &send_msg, &[])
+            .unwrap_err();
+        assert_eq!(res.to_string(), "InvalidToken");
+
+        // the order paid before the cutover is still settled in the old token
+        fulfill_order(&mut router, owner.clone(), coffee_swap_addr.clone(), Uint128::new(1));
+        let res = router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::TransferAllTokens {
+                    coffee_shop_key: Some(shop_key.clone()),
+                    token: None,
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(res.to_string(), "NotEnoughFunds");
+
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::TransferAllTokens {
+                    coffee_shop_key: Some(shop_key),
+                    token: Some(old_token.clone()),
+                },
+                &[],
+            )
+            .unwrap();
+        check_balance(&mut router, coffee_swap_addr, old_token.clone(), Uint128::zero());
+        check_balance(&mut router, owner, old_token.clone(), Uint128::new(900));
+        check_balance(&mut router, collector, old_token, Uint128::new(100));
+    }
+
+    #[test]
+    fn should_keep_shop_revenue_apart() {
+        let mut router = mock_app();
+
+        let owner = Addr::unchecked("owner");
+        let shop_owner = Addr::unchecked("shop_owner");
+        let alice_address = Addr::unchecked(ALICE);
+        let token_addr = instantiate_token(&mut router, owner.clone());
+        let amount = Uint128::new(10_000);
+        mint_some_token(
+            &mut router,
+            owner.clone(),
+            token_addr.clone(),
+            String::from(ALICE),
+            amount,
+        );
+
+        let coffee_swap_addr = instantiate_shop(
+            &mut router,
+            owner.clone(),
+            token_addr.clone(),
+            "astro".to_string(),
+        );
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &ExecuteMsg::CreateShop {
+                    shop_key: "downtown".to_string(),
+                    owner: shop_owner.clone(),
+                    menu: vec![CoffeeCup {
+                        name: String::from("Americano"),
+                        sizes: vec![CupSize {
+                            name: String::from("regular"),
+                            weight: Uint128::new(250),
+                            price: Uint128::new(1000),
+                            native_price: None,
+                        }],
+                    }],
+                    recipes: vec![CoffeeRecipe {
+                        ingredients: vec![IngredientCupShare {
+                            ingredient_type: String::from("water"),
+                            share: Uint128::new(100),
+                        }],
+                    }],
+                },
+                &[],
+            )
+            .unwrap();
+
+        let price = Uint128::new(1000);
+        for (shop_key, staff) in [("astro", &owner), ("downtown", &shop_owner)].iter() {
+            router
+                .execute_contract(
+                    (*staff).clone(),
+                    coffee_swap_addr.clone(),
+                    &ExecuteMsg::LoadIngredients {
+                        coffee_shop_key: shop_key.to_string(),
+                        portions: default_portions(Uint128::new(1000)),
+                    },
+                    &[],
+                )
+                .unwrap();
+            let send_msg = cw20::Cw20ExecuteMsg::Send {
+                contract: coffee_swap_addr.to_string(),
+                amount: price,
+                msg: to_binary(&ReceiveMsg::BuyCoffee {
+                    coffee_shop_key: shop_key.to_string(),
+                    id: Uint128::new(1),
+                    size: None,
+                    modifiers: vec![],
+                    amount: Uint128::new(1),
+                    expected_price: None,
+                    max_total: None,
+                })
+                .unwrap(),
+            };
+            router
+                .execute_contract(alice_address.clone(), token_addr.clone(), &send_msg, &[])
+                .unwrap();
+        }
+
+        // only the picked up order of astro is revenue, the other one is still in escrow
+        fulfill_order(
+            &mut router,
+            owner.clone(),
+            coffee_swap_addr.clone(),
+            Uint128::new(1),
+        );
+        let ledger = |router: &App, shop_key: &str| -> LedgerResponse {
+            router
+                .wrap()
+                .query_wasm_smart(
+                    &coffee_swap_addr,
+                    &QueryMsg::Ledger {
+                        coffee_shop_key: shop_key.to_string(),
+                    },
+                )
+                .unwrap()
+        };
+        assert_eq!(
+            ledger(&router, "astro").balances,
+            vec![Asset::token(token_addr.clone(), price)]
+        );
+        assert!(ledger(&router, "downtown").balances.is_empty());
+
+        let reconcile: ReconcileResponse = router
+            .wrap()
+            .query_wasm_smart(&coffee_swap_addr, &QueryMsg::Reconcile { token: None })
+            .unwrap();
+        assert_eq!(
+            reconcile,
+            ReconcileResponse {
+                token: token_addr.clone(),
+                balance: Uint128::new(2000),
+                ledger: price,
+                escrowed: price,
+                surplus: Uint128::zero(),
+                shortfall: Uint128::zero(),
+            }
+        );
+
+        let withdraw = |shop_key: Option<&str>| ExecuteMsg::TransferAllTokens {
+            coffee_shop_key: shop_key.map(String::from),
+            token: None,
+        };
+        let res = router
+            .execute_contract(
+                shop_owner.clone(),
+                coffee_swap_addr.clone(),
+                &withdraw(Some("downtown")),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(res.to_string(), "NotEnoughFunds");
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &withdraw(Some("astro")),
+                &[],
+            )
+            .unwrap();
+        check_balance(&mut router, owner.clone(), token_addr.clone(), price);
+        assert!(ledger(&router, "astro").balances.is_empty());
+        let history: WithdrawalsResponse = router
+            .wrap()
+            .query_wasm_smart(
+                &coffee_swap_addr,
+                &QueryMsg::Withdrawals {
+                    coffee_shop_key: "astro".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(history.withdrawals.len(), 1);
+        assert_eq!(
+            history.withdrawals[0].asset,
+            Asset::token(token_addr.clone(), price)
+        );
+        assert_eq!(history.withdrawals[0].recipient, owner);
+
+        // the revenue of a shop goes to its own owner, not to the contract owner
+        fulfill_order(
+            &mut router,
+            shop_owner.clone(),
+            coffee_swap_addr.clone(),
+            Uint128::new(2),
+        );
+        let res = router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &withdraw(Some("downtown")),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(res.to_string(), "Unauthorized");
+        router
+            .execute_contract(
+                shop_owner.clone(),
+                coffee_swap_addr.clone(),
+                &withdraw(Some("downtown")),
+                &[],
+            )
+            .unwrap();
+        check_balance(&mut router, shop_owner, token_addr.clone(), price);
+        check_balance(&mut router, owner.clone(), token_addr.clone(), price);
+
+        // tokens sent straight to the contract aren't owed to any shop
+        let transfer = cw20::Cw20ExecuteMsg::Transfer {
+            recipient: coffee_swap_addr.to_string(),
+            amount: Uint128::new(500),
+        };
+        router
+            .execute_contract(alice_address, token_addr.clone(), &transfer, &[])
+            .unwrap();
+        let reconcile: ReconcileResponse = router
+            .wrap()
+            .query_wasm_smart(&coffee_swap_addr, &QueryMsg::Reconcile { token: None })
+            .unwrap();
+        assert_eq!(reconcile.surplus, Uint128::new(500));
+        router
+            .execute_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &withdraw(None),
+                &[],
+            )
+            .unwrap();
+        check_balance(&mut router, owner, token_addr.clone(), Uint128::new(1500));
+        check_balance(
+            &mut router,
+            coffee_swap_addr.clone(),
+            token_addr,
+            Uint128::zero(),
+        );
+
+        // a failing balance query is reported, not taken for a zero balance
+        let res: StdResult<ReconcileResponse> = router.wrap().query_wasm_smart(
+            &coffee_swap_addr,
+            &QueryMsg::Reconcile {
+                token: Some(Addr::unchecked("not_a_token")),
+            },
+        );
+        assert!(res.is_err());
+    }
+
+    // a contract stored by the v0.1.0 code, so a deployed shop can be upgraded from it
+    #[derive(Serialize, Deserialize)]
+    struct LegacyState {
+        owner: Addr,
+        balance: Uint128,
+        coffee_token_addr: Addr,
+    }
+
+    fn legacy_instantiate(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        msg: InstantiateMsg,
+    ) -> StdResult<Response> {
+        let state = LegacyState {
+            owner: info.sender,
+            balance: Uint128::zero(),
+            coffee_token_addr: msg.token_addr,
+        };
+        Item::<LegacyState>::new("state").save(deps.storage, &state)?;
+
+        let share = |ingredient_type: LegacyIngredient, share: u128| LegacyIngredientCupShare {
+            ingredient_type,
+            share: Uint128::new(share),
+        };
+        let portion = |ingredient: LegacyIngredient| LegacyIngredientPortion {
+            ingredient,
+            weight: Uint128::new(1000),
+        };
+        let legacy = LegacyCoffeeState {
+            menu: vec![
+                LegacyCoffeeCup {
+                    name: String::from("Cappuccino"),
+                    price: Uint128::new(100),
+                },
+                LegacyCoffeeCup {
+                    name: String::from("Americano"),
+                    price: Uint128::new(70),
+                },
+            ],
+            recipes: vec![
+                LegacyCoffeeRecipe {
+                    ingredients: vec![
+                        share(LegacyIngredient::Water, 45),
+                        share(LegacyIngredient::Beans, 25),
+                        share(LegacyIngredient::Milk, 30),
+                    ],
+                },
+                LegacyCoffeeRecipe {
+                    ingredients: vec![
+                        share(LegacyIngredient::Water, 75),
+                        share(LegacyIngredient::Beans, 25),
+                    ],
+                },
+            ],
+            ingredient_portions: vec![
+                portion(LegacyIngredient::Water),
+                portion(LegacyIngredient::Beans),
+                portion(LegacyIngredient::Milk),
+            ],
+        };
+        LEGACY_COFFEE_STATE.save(deps.storage, msg.shop_key, &legacy)?;
+
+        set_contract_version(deps.storage, "crates.io:shop", "0.1.0")?;
+        Ok(Response::new())
+    }
+
+    fn legacy_execute(_: DepsMut, _: Env, _: MessageInfo, _: Empty) -> StdResult<Response> {
+        Err(StdError::generic_err("not supported"))
+    }
+
+    fn legacy_query(_: Deps, _: Env, _: Empty) -> StdResult<Binary> {
+        Err(StdError::generic_err("not supported"))
+    }
+
+    #[test]
+    fn should_migrate_from_v0_1_0() {
+        let mut router = mock_app();
+
+        let owner = Addr::unchecked("owner");
+        let alice_address = Addr::unchecked(ALICE);
+        let token_addr = instantiate_token(&mut router, owner.clone());
+        let amount = Uint128::new(1000);
+        mint_some_token(
+            &mut router,
+            owner.clone(),
+            token_addr.clone(),
+            String::from(ALICE),
+            amount,
+        );
+
+        let legacy_id = router.store_code(Box::new(ContractWrapper::new(
+            legacy_execute,
+            legacy_instantiate,
+            legacy_query,
+        )));
+        let shop_id = router.store_code(contract_coffee_swap());
+
+        let shop_key = "astro".to_string();
+        let msg = InstantiateMsg {
+            token_addr: token_addr.clone(),
+            shop_key: shop_key.clone(),
+        };
+        let coffee_swap_addr = router
+            .instantiate_contract(
+                legacy_id,
+                owner.clone(),
+                &msg,
+                &[],
+                "Shop",
+                Some(owner.to_string()),
+            )
+            .unwrap();
+
+        let res = router
+            .migrate_contract(
+                owner.clone(),
+                coffee_swap_addr.clone(),
+                &MigrateMsg {},
+                shop_id,
+            )
+            .unwrap();
+        let event = res.events.iter().find(|event| event.ty == "wasm").unwrap();
+        assert!(event.attributes.contains(&attr("from_version", "0.1.0")));
+        assert!(event.attributes.contains(&attr("migrations", "0.2.0")));
+
+        let config: ConfigResponse = router
+            .wrap()
+            .query_wasm_smart(&coffee_swap_addr, &QueryMsg::Config {})
+            .unwrap();
+        assert_eq!(config.owner, Some(owner.clone()));
+        assert_eq!(config.coffee_token_addr, token_addr);
+        assert_eq!(config.pause, None);
+
+        // the positions became the item ids
+        let menu: MenuResponse = router
+            .wrap()
+            .query_wasm_smart(
+                &coffee_swap_addr,
+                &QueryMsg::Menu {
+                    coffee_shop_key: shop_key.clone(),
+                },
+            )
+            .unwrap();
+        let items: Vec<_> = menu
+            .menu
+            .iter()
+            .map(|entry| (entry.item.id.u128(), entry.item.cup.name.as_str()))
+            .collect();
+        assert_eq!(items, vec![(1, "Cappuccino"), (2, "Americano")]);
+        assert!(menu.menu.iter().all(|entry| entry.available));
+
+        // the upgraded shop sells coffee from the migrated stock
+        let price = Uint128::new(70);
+        let send_msg = cw20::Cw20ExecuteMsg::Send {
+            contract: coffee_swap_addr.to_string(),
+            amount: price,
+            msg: to_binary(&ReceiveMsg::BuyCoffee {
+                coffee_shop_key: shop_key,
+                id: Uint128::new(2),
+                size: None,
+                modifiers: vec![],
+                amount: Uint128::new(1),
+                expected_price: Some(price),
+                max_total: None,
+            })
+            .unwrap(),
+        };
+        router
+            .execute_contract(alice_address, token_addr.clone(), &send_msg, &[])
+            .unwrap();
+        check_balance(&mut router, coffee_swap_addr.clone(), token_addr, price);
+
+        // migrating to the same version is a no-op
+        router
+            .migrate_contract(owner, coffee_swap_addr, &MigrateMsg {}, shop_id)
+            .unwrap();
     }
 }
@@ -1,8 +1,13 @@
+pub mod asset;
 pub mod coffee_state;
 pub mod contract;
 mod error;
 mod integration;
+mod migration;
 pub mod msg;
+pub mod order;
 pub mod products;
+pub mod roles;
 pub mod state;
 mod token;
+pub mod withdrawal;
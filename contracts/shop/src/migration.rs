@@ -0,0 +1,369 @@
+use cosmwasm_std::{Order, StdError, StdResult, Storage, Uint128};
+use cw_storage_plus::Map;
+use serde::{Deserialize, Serialize};
+
+use crate::coffee_state::{save_new_shop, CoffeeState};
+use crate::contract::{
+    default_ingredient_catalogue, default_late_recipe, BEANS, MILK, SUGAR, WATER,
+};
+use crate::error::ContractError;
+use crate::products::{
+    CoffeeCup, CoffeeRecipe, CupSize, IngredientCupShare, AVERAGE_CUP_WEIGHT, DEFAULT_SIZE,
+};
+use crate::state::STATE;
+
+type Migration = fn(&mut dyn Storage) -> StdResult<()>;
+
+// every step brings the storage up to the layout of the version it's listed with,
+// a contract runs the steps of the versions newer than its stored one, in this order
+pub const MIGRATIONS: &[(&str, Migration)] = &[
+    // v0.1.0 addressed menu items and recipes by their position
+    ("0.2.0", migrate_from_v0_1_0),
+];
+
+// major.minor.patch, pre-release and build tags aren't used by this contract
+pub fn parse_version(version: &str) -> Result<(u64, u64, u64), ContractError> {
+    let invalid = || ContractError::InvalidVersion {
+        version: version.to_string(),
+    };
+    let parts = version
+        .split('.')
+        .map(|part| part.parse::<u64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [major, minor, patch] => Ok((major, minor, patch)),
+        _ => Err(invalid()),
+    }
+}
+
+// runs the steps newer than `from` and up to `to`, returns the versions that were migrated to
+pub fn run_migrations(
+    storage: &mut dyn Storage,
+    from: &str,
+    to: &str,
+) -> Result<Vec<&'static str>, ContractError> {
+    let from_version = parse_version(from)?;
+    let to_version = parse_version(to)?;
+    if from_version > to_version {
+        return Err(ContractError::CannotDowngrade {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+    }
+
+    let mut applied = vec![];
+    for (version, migration) in MIGRATIONS {
+        let step_version = parse_version(version)?;
+        if from_version < step_version && step_version <= to_version {
+            migration(storage)?;
+            applied.push(*version);
+        }
+    }
+    Ok(applied)
+}
+
+// v0.1.0 layout: menu[i] and recipes[i] are linked by their position only,
+// ingredients are a fixed enum
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LegacyCoffeeCup {
+    pub name: String,
+    pub price: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LegacyCoffeeState {
+    pub menu: Vec<LegacyCoffeeCup>,
+    pub recipes: Vec<LegacyCoffeeRecipe>,
+    pub ingredient_portions: Vec<LegacyIngredientPortion>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LegacyCoffeeRecipe {
+    pub ingredients: Vec<LegacyIngredientCupShare>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LegacyIngredientCupShare {
+    pub ingredient_type: LegacyIngredient,
+    pub share: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LegacyIngredientPortion {
+    pub ingredient: LegacyIngredient,
+    pub weight: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum LegacyIngredient {
+    Sugar,
+    Milk,
+    Water,
+    Beans,
+}
+
+impl LegacyIngredient {
+    // the id of the ingredient in the default catalogue
+    fn id(&self) -> String {
+        let id = match self {
+            LegacyIngredient::Sugar => SUGAR,
+            LegacyIngredient::Milk => MILK,
+            LegacyIngredient::Water => WATER,
+            LegacyIngredient::Beans => BEANS,
+        };
+        String::from(id)
+    }
+}
+
+// the Late v0.1.0 was instantiated with, it listed the beans twice and had no milk
+fn v0_1_0_late_recipe() -> LegacyCoffeeRecipe {
+    let share = |ingredient_type, share| LegacyIngredientCupShare {
+        ingredient_type,
+        share: Uint128::new(share),
+    };
+    LegacyCoffeeRecipe {
+        ingredients: vec![
+            share(LegacyIngredient::Beans, 2),
+            share(LegacyIngredient::Water, 45),
+            share(LegacyIngredient::Beans, 25),
+        ],
+    }
+}
+
+// recipes could list an ingredient twice, such shares are merged,
+// an unchanged default Late becomes the one instantiate stores, so both deployments brew it alike
+fn convert_recipe(legacy: LegacyCoffeeRecipe) -> CoffeeRecipe {
+    if legacy == v0_1_0_late_recipe() {
+        return default_late_recipe();
+    }
+    let mut ingredients: Vec<IngredientCupShare> = vec![];
+    for legacy_share in legacy.ingredients {
+        let ingredient_type = legacy_share.ingredient_type.id();
+        match ingredients
+            .iter_mut()
+            .find(|share| share.ingredient_type == ingredient_type)
+        {
+            Some(share) => share.share += legacy_share.share,
+            None => ingredients.push(IngredientCupShare {
+                ingredient_type,
+                share: legacy_share.share,
+            }),
+        }
+    }
+    CoffeeRecipe { ingredients }
+}
+
+pub const LEGACY_COFFEE_STATE: Map<String, LegacyCoffeeState> = Map::new("coffee_state");
+
+// converts every shop to menu items owning their recipes,
+// the old 1-based positions become the item ids so integrations keep working
+pub fn migrate_from_v0_1_0(storage: &mut dyn Storage) -> StdResult<()> {
+    let owner = STATE
+        .load(storage)?
+        .owner
+        .ok_or_else(|| StdError::generic_err("the contract has no owner"))?;
+
+    let shops = LEGACY_COFFEE_STATE
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (key, legacy) in shops {
+        let shop_key = String::from_utf8(key).map_err(StdError::invalid_utf8)?;
+        let menu = legacy
+            .menu
+            .into_iter()
+            .map(|cup| CoffeeCup {
+                name: cup.name,
+                sizes: vec![CupSize {
+                    name: String::from(DEFAULT_SIZE),
+                    weight: Uint128::new(AVERAGE_CUP_WEIGHT),
+                    price: cup.price,
+                    native_price: None,
+                }],
+            })
+            .collect();
+
+        let recipes = legacy.recipes.into_iter().map(convert_recipe).collect();
+
+        // an item without a recipe at its position can't be brewed and is dropped
+        let mut coffee_state =
+            CoffeeState::new(owner.clone(), menu, recipes, default_ingredient_catalogue());
+        for legacy_portion in legacy.ingredient_portions {
+            let ingredient = legacy_portion.ingredient.id();
+            if let Some(portion) = coffee_state
+                .ingredient_portions
+                .iter_mut()
+                .find(|portion| portion.ingredient == ingredient)
+            {
+                portion.weight += legacy_portion.weight;
+            }
+        }
+        save_new_shop(storage, &shop_key, &coffee_state)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_dependencies, mock_env};
+    use cosmwasm_std::{Addr, Uint128};
+    use cw2::{get_contract_version, set_contract_version};
+
+    use crate::coffee_state::{COFFEE_STATE, SHOPS_BY_OWNER};
+    use crate::contract::{default_late_recipe, migrate};
+    use crate::error::ContractError;
+    use crate::migration::{
+        run_migrations, LegacyCoffeeCup, LegacyCoffeeRecipe, LegacyCoffeeState, LegacyIngredient,
+        LegacyIngredientCupShare, LegacyIngredientPortion, LEGACY_COFFEE_STATE,
+    };
+    use crate::msg::MigrateMsg;
+    use crate::products::{CoffeeRecipe, IngredientCupShare, IngredientPortion};
+    use crate::state::{State, DEFAULT_ORDER_TIMEOUT, STATE};
+
+    fn legacy_share(ingredient_type: LegacyIngredient, share: u128) -> LegacyIngredientCupShare {
+        LegacyIngredientCupShare {
+            ingredient_type,
+            share: Uint128::new(share),
+        }
+    }
+
+    #[test]
+    fn migrate_from_v0_1_0_test() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = Addr::unchecked("creator");
+
+        let state = State {
+            owner: Some(owner.clone()),
+            coffee_token_addr: Addr::unchecked("coffee_token"),
+            previous_token_addrs: vec![],
+            order_timeout: DEFAULT_ORDER_TIMEOUT,
+            pause: None,
+            default_cup_weight: Uint128::new(250),
+            fee: None,
+        };
+        STATE.save(deps.as_mut().storage, &state).unwrap();
+        set_contract_version(deps.as_mut().storage, "crates.io:shop", "0.1.0").unwrap();
+
+        let legacy = LegacyCoffeeState {
+            menu: vec![
+                LegacyCoffeeCup {
+                    name: String::from("Cappuccino"),
+                    price: Uint128::new(1000),
+                },
+                LegacyCoffeeCup {
+                    name: String::from("Late"),
+                    price: Uint128::new(1000),
+                },
+                LegacyCoffeeCup {
+                    name: String::from("Americano"),
+                    price: Uint128::new(900),
+                },
+            ],
+            recipes: vec![
+                LegacyCoffeeRecipe {
+                    ingredients: vec![legacy_share(LegacyIngredient::Milk, 100)],
+                },
+                // the default Late of v0.1.0
+                LegacyCoffeeRecipe {
+                    ingredients: vec![
+                        legacy_share(LegacyIngredient::Beans, 2),
+                        legacy_share(LegacyIngredient::Water, 45),
+                        legacy_share(LegacyIngredient::Beans, 25),
+                    ],
+                },
+                LegacyCoffeeRecipe {
+                    ingredients: vec![
+                        legacy_share(LegacyIngredient::Beans, 10),
+                        legacy_share(LegacyIngredient::Water, 70),
+                        legacy_share(LegacyIngredient::Beans, 15),
+                    ],
+                },
+            ],
+            ingredient_portions: vec![LegacyIngredientPortion {
+                ingredient: LegacyIngredient::Water,
+                weight: Uint128::new(500),
+            }],
+        };
+        LEGACY_COFFEE_STATE
+            .save(deps.as_mut().storage, "shop".to_string(), &legacy)
+            .unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+        let coffee_state = COFFEE_STATE
+            .load(deps.as_ref().storage, "shop".to_string())
+            .unwrap();
+        assert_eq!(coffee_state.owner, owner);
+        assert!(SHOPS_BY_OWNER.has(deps.as_ref().storage, (owner, "shop".to_string())));
+        assert_eq!(coffee_state.last_item_id, Uint128::new(3));
+        // the default Late is brewed the way a newly instantiated shop brews it
+        assert_eq!(coffee_state.menu[1].cup.name, "Late");
+        assert_eq!(coffee_state.menu[1].recipe, default_late_recipe());
+        assert_eq!(coffee_state.menu[2].id, Uint128::new(3));
+        assert_eq!(coffee_state.menu[2].cup.name, "Americano");
+        assert_eq!(
+            coffee_state.menu[2].recipe,
+            CoffeeRecipe {
+                ingredients: vec![
+                    IngredientCupShare {
+                        ingredient_type: String::from("beans"),
+                        share: Uint128::new(25),
+                    },
+                    IngredientCupShare {
+                        ingredient_type: String::from("water"),
+                        share: Uint128::new(70),
+                    },
+                ],
+            }
+        );
+        assert_eq!(coffee_state.ingredients.len(), 4);
+        assert!(coffee_state.ingredient_portions.contains(&IngredientPortion {
+            ingredient: String::from("water"),
+            weight: Uint128::new(500),
+        }));
+
+        let version = get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn migrate_checks_stored_version() {
+        let mut deps = mock_dependencies(&[]);
+
+        set_contract_version(deps.as_mut().storage, "crates.io:other", "0.1.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::WrongContract {
+                name: "crates.io:other".to_string()
+            }
+        );
+
+        set_contract_version(deps.as_mut().storage, "crates.io:shop", "9.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CannotDowngrade {
+                from: "9.0.0".to_string(),
+                to: env!("CARGO_PKG_VERSION").to_string()
+            }
+        );
+
+        set_contract_version(deps.as_mut().storage, "crates.io:shop", "0.1").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidVersion {
+                version: "0.1".to_string()
+            }
+        );
+
+        // nothing to run from the current version
+        let version = env!("CARGO_PKG_VERSION");
+        set_contract_version(deps.as_mut().storage, "crates.io:shop", version).unwrap();
+        assert_eq!(
+            run_migrations(deps.as_mut().storage, version, version).unwrap(),
+            Vec::<&str>::new()
+        );
+    }
+}
@@ -1,8 +1,14 @@
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Coin, Timestamp, Uint128};
+use cw20::{Cw20ReceiveMsg, Expiration};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::products::{IngredientPortion};
+use crate::asset::AssetInfo;
+use crate::products::{
+    CoffeeCup, CoffeeRecipe, IngredientInfo, IngredientPortion, Modifier, OrderLine,
+};
+use crate::roles::Role;
+use crate::state::FeeConfig;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
@@ -10,33 +16,225 @@ pub struct InstantiateMsg {
     pub shop_key: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
+    CreateShop {
+        shop_key: String,
+        owner: Addr,
+        menu: Vec<CoffeeCup>,
+        recipes: Vec<CoffeeRecipe>,
+    },
+    // shop owners grant and revoke the roles of the shop staff
+    GrantRole {
+        coffee_shop_key: String,
+        addr: Addr,
+        role: Role,
+    },
+    RevokeRole {
+        coffee_shop_key: String,
+        addr: Addr,
+        role: Role,
+    },
+    SetShopActive {
+        coffee_shop_key: String,
+        active: bool,
+    },
+    RegisterIngredient {
+        coffee_shop_key: String,
+        ingredient: IngredientInfo,
+    },
     LoadIngredients {
         coffee_shop_key: String,
         portions: Vec<IngredientPortion>,
     },
+    // takes spoiled or spilled ingredients out of the stock
+    WriteOffIngredients {
+        coffee_shop_key: String,
+        portions: Vec<IngredientPortion>,
+        reason: Option<String>,
+    },
+    AddMenuItem {
+        coffee_shop_key: String,
+        cup: CoffeeCup,
+        recipe: CoffeeRecipe,
+    },
+    UpdateMenuItem {
+        coffee_shop_key: String,
+        id: Uint128,
+        cup: CoffeeCup,
+        recipe: CoffeeRecipe,
+    },
+    RemoveMenuItem {
+        coffee_shop_key: String,
+        id: Uint128,
+    },
+    // lists every menu item id in the new display order
+    ReorderMenu {
+        coffee_shop_key: String,
+        ids: Vec<Uint128>,
+    },
+    // replaces the modifiers customers can choose for the item
+    SetModifiers {
+        coffee_shop_key: String,
+        id: Uint128,
+        modifiers: Vec<Modifier>,
+    },
+    // `size` defaults to the first size of the item
     SetPrice {
         coffee_shop_key: String,
         id: Uint128,
+        size: Option<String>,
         price: Uint128,
     },
+    SetNativePrice {
+        coffee_shop_key: String,
+        id: Uint128,
+        size: Option<String>,
+        price: Option<Coin>,
+    },
+    // paid in native coins when `funds` are attached, otherwise via cw20 TransferFrom
+    BuyCoffee {
+        coffee_shop_key: String,
+        id: Uint128,
+        size: Option<String>,
+        // ids of the item's modifiers
+        #[serde(default)]
+        modifiers: Vec<String>,
+        amount: Uint128,
+        // the purchase fails if the price of a cup or the total is higher
+        expected_price: Option<Uint128>,
+        max_total: Option<Uint128>,
+    },
+    // several items paid at once, the ingredients of all lines are checked before any is taken
+    PlaceOrder {
+        coffee_shop_key: String,
+        lines: Vec<OrderLine>,
+        max_total: Option<Uint128>,
+    },
+    // shop staff move an order through Placed -> Brewing -> Ready -> PickedUp
+    StartBrewing {
+        order_id: Uint128,
+    },
+    MarkOrderReady {
+        order_id: Uint128,
+    },
+    MarkOrderPickedUp {
+        order_id: Uint128,
+    },
+    // the customer calls off an order that isn't brewing yet
+    CancelOrder {
+        order_id: Uint128,
+        reason: Option<String>,
+    },
+    // shop staff give back the payment of an order, optionally returning its ingredients to the stock
+    RefundOrder {
+        order_id: Uint128,
+        restore_ingredients: bool,
+        reason: Option<String>,
+    },
+    // the customer takes back the payment of an order that wasn't picked up before it expired
+    ReclaimPayment {
+        order_id: Uint128,
+    },
+    // contract owner sets how long new orders wait before the payment can be reclaimed
+    SetOrderTimeout {
+        seconds: u64,
+    },
+    // contract owner changes the settings that are given, open orders keep the token they
+    // were paid with and the balance of a replaced token can still be withdrawn
+    UpdateConfig {
+        token_addr: Option<Addr>,
+        default_cup_weight: Option<Uint128>,
+        // a zero rate turns the fee off
+        fee: Option<FeeConfig>,
+    },
+    // stops the sales of the shop, or of every shop when no key is given, while
+    // withdrawals and configuration keep working
+    Pause {
+        coffee_shop_key: Option<String>,
+        reason: Option<String>,
+        expires: Option<Timestamp>,
+    },
+    Unpause {
+        coffee_shop_key: Option<String>,
+    },
+    // the proposed owner takes over the contract, and the shops of the current owner, by
+    // accepting before the proposal expires
+    ProposeNewOwner {
+        owner: Addr,
+        expires: Option<Expiration>,
+    },
+    AcceptOwnership {},
+    CancelOwnershipProposal {},
+    // leaves the contract without an owner, this can't be undone, the shops keep their owner
+    RenounceOwnership {},
+    // the shop owner or treasurer sends the revenue of the shop in the payment token, or in a
+    // token it replaced, to the shop owner, without a shop the contract owner takes the
+    // balance that isn't owed to any shop or held for open orders
+    TransferAllTokens {
+        coffee_shop_key: Option<String>,
+        token: Option<Addr>,
+    },
+    TransferAllNativeTokens {
+        coffee_shop_key: Option<String>,
+        denom: String,
+    },
+    // pays part of the revenue of the shop to any recipient, done by the shop owner or
+    // treasurer, the fee is taken out of the amount
+    Withdraw {
+        coffee_shop_key: String,
+        token: AssetInfo,
+        amount: Uint128,
+        recipient: Addr,
+    },
+    // Cw20 hook: buy coffee with a single `Send` of coffee tokens
+    Receive(Cw20ReceiveMsg),
+}
+
+// Payloads embedded into the `msg` field of a Cw20 `Send`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
     BuyCoffee {
         coffee_shop_key: String,
         id: Uint128,
+        size: Option<String>,
+        // ids of the item's modifiers
+        #[serde(default)]
+        modifiers: Vec<String>,
         amount: Uint128,
+        // the purchase fails if the price of a cup or the total is higher
+        expected_price: Option<Uint128>,
+        max_total: Option<Uint128>,
+    },
+    // several items paid at once, the ingredients of all lines are checked before any is taken
+    PlaceOrder {
+        coffee_shop_key: String,
+        lines: Vec<OrderLine>,
+        max_total: Option<Uint128>,
     },
-    TransferAllTokens {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Owner {},
+    Config {},
+    ShopInfo {
+        coffee_shop_key: String,
+    },
+    ListShops {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
     Price {
         coffee_shop_key: String,
         id: Uint128,
+        size: Option<String>,
     },
     Menu {
         coffee_shop_key: String,
@@ -47,7 +245,57 @@ pub enum QueryMsg {
     Ingredients {
         coffee_shop_key: String,
     },
-    // Implements CW20. Returns the current balance of the given address, 0 if unset.
+    // how many cups of every menu item the current stock allows
+    Availability {
+        coffee_shop_key: String,
+    },
+    // what buying `amount` cups would cost and take from the stock
+    SimulatePurchase {
+        coffee_shop_key: String,
+        item: Uint128,
+        size: Option<String>,
+        #[serde(default)]
+        modifiers: Vec<String>,
+        amount: Uint128,
+    },
+    Order {
+        order_id: Uint128,
+    },
+    // open orders of the customer
+    CustomerOrders {
+        customer: Addr,
+        start_after: Option<Uint128>,
+        limit: Option<u32>,
+    },
+    // open orders of the shop, oldest first
+    ShopQueue {
+        coffee_shop_key: String,
+        start_after: Option<Uint128>,
+        limit: Option<u32>,
+    },
+    // revenue of the shop that can be withdrawn, by asset
+    Ledger {
+        coffee_shop_key: String,
+    },
+    // withdrawals from the ledger of the shop, oldest first
+    Withdrawals {
+        coffee_shop_key: String,
+        start_after: Option<Uint128>,
+        limit: Option<u32>,
+    },
+    // the shop ledgers and the escrow against the balance the token reports,
+    // for the payment token when none is given
+    Reconcile {
+        token: Option<Addr>,
+    },
+    // staff of the shop and their roles
+    Roles {
+        coffee_shop_key: String,
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    },
+    // Implements CW20. Returns the current balance of the given address, an error of the token
+    // query is returned as is rather than read as a zero balance
     Balance {
         contract_address: Addr,
         address: Addr,
@@ -0,0 +1,114 @@
+use std::convert::TryInto;
+
+use cosmwasm_std::{Addr, Empty, StdError, StdResult, Storage, Timestamp, Uint128};
+use cw_storage_plus::{Item, Map, U128Key};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::asset::Asset;
+use crate::products::{IngredientPortion, OrderLine};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    Placed,
+    Brewing,
+    Ready,
+    PickedUp,
+    Cancelled,
+    Refunded,
+}
+
+impl OrderStatus {
+    // staff move an order one step at a time: Placed -> Brewing -> Ready -> PickedUp
+    pub fn can_advance_to(&self, next: &OrderStatus) -> bool {
+        matches!(
+            (self, next),
+            (OrderStatus::Placed, OrderStatus::Brewing)
+                | (OrderStatus::Brewing, OrderStatus::Ready)
+                | (OrderStatus::Ready, OrderStatus::PickedUp)
+        )
+    }
+
+    // picked up, cancelled and refunded orders are closed
+    pub fn is_open(&self) -> bool {
+        !matches!(
+            self,
+            OrderStatus::PickedUp | OrderStatus::Cancelled | OrderStatus::Refunded
+        )
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Placed => "placed",
+            OrderStatus::Brewing => "brewing",
+            OrderStatus::Ready => "ready",
+            OrderStatus::PickedUp => "picked_up",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CoffeeOrder {
+    pub id: Uint128,
+    pub shop_key: String,
+    pub customer: Addr,
+    pub lines: Vec<OrderLine>,
+    // taken from the stock when the order was placed
+    #[serde(default)]
+    pub ingredients: Vec<IngredientPortion>,
+    // held in escrow until the order is picked up
+    pub payment: Asset,
+    pub status: OrderStatus,
+    pub placed_at: Timestamp,
+    // the customer can reclaim the payment of an open order from then on
+    pub expires_at: Timestamp,
+    // why the order was cancelled or refunded
+    pub reason: Option<String>,
+}
+
+pub const ORDERS: Map<U128Key, CoffeeOrder> = Map::new("orders");
+// ids are never reused, like the menu item ids
+pub const LAST_ORDER_ID: Item<Uint128> = Item::new("last_order_id");
+
+// open orders only, so the customer and barista views don't walk the order history
+pub const CUSTOMER_ORDERS: Map<(Addr, U128Key), Empty> = Map::new("customer_orders");
+pub const SHOP_QUEUE: Map<(String, U128Key), Empty> = Map::new("shop_queue");
+
+pub fn next_order_id(storage: &mut dyn Storage) -> StdResult<Uint128> {
+    let id = LAST_ORDER_ID.may_load(storage)?.unwrap_or_default() + Uint128::new(1);
+    LAST_ORDER_ID.save(storage, &id)?;
+    Ok(id)
+}
+
+// order ids are stored big-endian in the index keys
+pub fn order_id_from_key(key: &[u8]) -> StdResult<Uint128> {
+    let bytes: [u8; 16] = key
+        .try_into()
+        .map_err(|_| StdError::generic_err("invalid order key"))?;
+    Ok(Uint128::new(u128::from_be_bytes(bytes)))
+}
+
+impl CoffeeOrder {
+    // saves the order and keeps the open order indexes in line with its status
+    pub fn save(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        ORDERS.save(storage, self.key(), self)?;
+
+        let by_customer = (self.customer.clone(), self.key());
+        let by_shop = (self.shop_key.clone(), self.key());
+        if self.status.is_open() {
+            CUSTOMER_ORDERS.save(storage, by_customer, &Empty {})?;
+            SHOP_QUEUE.save(storage, by_shop, &Empty {})?;
+        } else {
+            CUSTOMER_ORDERS.remove(storage, by_customer);
+            SHOP_QUEUE.remove(storage, by_shop);
+        }
+        Ok(())
+    }
+
+    fn key(&self) -> U128Key {
+        U128Key::new(self.id.u128())
+    }
+}
@@ -1,18 +1,229 @@
-use std::ops::{Mul};
-
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Coin, StdError, Uint128};
+use cw20::Expiration;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::asset::Asset;
+use crate::error::ContractError;
+use crate::order::CoffeeOrder;
+use crate::roles::Role;
+use crate::state::{FeeConfig, PauseInfo};
+use crate::withdrawal::Withdrawal;
+
 // share like %
 pub const SHARE_PRECISION: Uint128 = Uint128::new(100);
 
 pub const AVERAGE_CUP_WEIGHT: u128 = 250u128;
 
+// size of the items that were sold in a single size
+pub const DEFAULT_SIZE: &str = "regular";
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct CoffeeCup {
     pub name: String,
+    // the first size is used when an order doesn't name one
+    pub sizes: Vec<CupSize>,
+}
+
+impl CoffeeCup {
+    pub fn size_index(&self, size: Option<&str>) -> Option<usize> {
+        match size {
+            None if self.sizes.is_empty() => None,
+            None => Some(0),
+            Some(name) => self.sizes.iter().position(|cup_size| cup_size.name == name),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CupSize {
+    // e.g. small, medium, large
+    pub name: String,
+    // total weight of the ingredients in a cup of this size
+    pub weight: Uint128,
     pub price: Uint128,
+    // optional price in the chain's native coins, paid through `funds`
+    pub native_price: Option<Coin>,
+}
+
+// a menu entry keeps its id when other entries are removed or reordered,
+// the recipe belongs to the entry so the two can't get out of alignment
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MenuItem {
+    pub id: Uint128,
+    pub cup: CoffeeCup,
+    pub recipe: CoffeeRecipe,
+    // options a customer can pick when ordering the item
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+}
+
+// e.g. { id: "oat", name: "Oat milk", price_delta: 50, effects: [Substitute { from: "milk", to: "oat_milk" }] }
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Modifier {
+    pub id: String,
+    pub name: String,
+    // added to the price of every cup, in the coffee token and in the size's native denom
+    pub price_delta: Uint128,
+    pub native_price_delta: Uint128,
+    pub effects: Vec<ModifierEffect>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModifierEffect {
+    // raises the ingredient's share, e.g. an extra shot
+    Add { ingredient: String, share: Uint128 },
+    // drops the ingredient from the recipe, e.g. no sugar
+    Remove { ingredient: String },
+    // the substitute takes over the share of the replaced ingredient
+    Substitute { from: String, to: String },
+}
+
+// a single item of an order
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderLine {
+    // id of the menu item
+    pub item: Uint128,
+    // the item's first size when empty
+    pub size: Option<String>,
+    // ids of the item's modifiers, applied in the given order
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    pub quantity: Uint128,
+    // the order fails if a cup of the line costs more
+    pub expected_price: Option<Uint128>,
+}
+
+// checks the modifiers of an item against the shop's `catalogue` of ingredients
+pub fn validate_modifiers(
+    modifiers: &[Modifier],
+    catalogue: &[IngredientInfo],
+) -> Result<(), ContractError> {
+    let registered = |ingredient: &String| -> Result<(), ContractError> {
+        if catalogue.iter().any(|info| info.id == *ingredient) {
+            Ok(())
+        } else {
+            Err(ContractError::UnknownIngredient {
+                ingredient: ingredient.clone(),
+            })
+        }
+    };
+
+    for (i, modifier) in modifiers.iter().enumerate() {
+        if modifier.id.is_empty() || modifier.name.is_empty() || modifier.effects.is_empty() {
+            return Err(ContractError::InvalidParam {});
+        }
+        if modifiers[..i].iter().any(|other| other.id == modifier.id) {
+            return Err(ContractError::InvalidParam {});
+        }
+        for effect in modifier.effects.iter() {
+            match effect {
+                ModifierEffect::Add { ingredient, share } => {
+                    if share.is_zero() {
+                        return Err(ContractError::ZeroShare {
+                            ingredient: ingredient.clone(),
+                        });
+                    }
+                    registered(ingredient)?;
+                }
+                ModifierEffect::Remove { ingredient } => registered(ingredient)?,
+                ModifierEffect::Substitute { from, to } => {
+                    if from == to {
+                        return Err(ContractError::InvalidParam {});
+                    }
+                    registered(from)?;
+                    registered(to)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// returns the recipe with the effects of the modifiers applied
+pub fn apply_modifiers(recipe: &CoffeeRecipe, modifiers: &[&Modifier]) -> CoffeeRecipe {
+    let mut ingredients = recipe.ingredients.clone();
+    for effect in modifiers.iter().flat_map(|modifier| modifier.effects.iter()) {
+        match effect {
+            ModifierEffect::Add { ingredient, share } => {
+                match ingredients
+                    .iter_mut()
+                    .find(|cup_share| cup_share.ingredient_type == *ingredient)
+                {
+                    Some(cup_share) => cup_share.share = cup_share.share.saturating_add(*share),
+                    None => ingredients.push(IngredientCupShare {
+                        ingredient_type: ingredient.clone(),
+                        share: *share,
+                    }),
+                }
+            }
+            ModifierEffect::Remove { ingredient } => {
+                ingredients.retain(|cup_share| cup_share.ingredient_type != *ingredient)
+            }
+            ModifierEffect::Substitute { from, to } => {
+                let share = match ingredients
+                    .iter()
+                    .position(|cup_share| cup_share.ingredient_type == *from)
+                {
+                    Some(index) => ingredients.remove(index).share,
+                    None => continue,
+                };
+                match ingredients
+                    .iter_mut()
+                    .find(|cup_share| cup_share.ingredient_type == *to)
+                {
+                    Some(cup_share) => cup_share.share = cup_share.share.saturating_add(share),
+                    None => ingredients.push(IngredientCupShare {
+                        ingredient_type: to.clone(),
+                        share,
+                    }),
+                }
+            }
+        }
+    }
+    CoffeeRecipe { ingredients }
+}
+
+// checks a recipe before it's stored for a shop with the `catalogue` of ingredients
+pub fn validate_recipe(
+    recipe: &CoffeeRecipe,
+    catalogue: &[IngredientInfo],
+    share_precision: Uint128,
+) -> Result<(), ContractError> {
+    let mut total = Uint128::zero();
+    for (i, ingredient) in recipe.ingredients.iter().enumerate() {
+        if recipe.ingredients[..i]
+            .iter()
+            .any(|other| other.ingredient_type == ingredient.ingredient_type)
+        {
+            return Err(ContractError::DuplicateIngredient {
+                ingredient: ingredient.ingredient_type.clone(),
+            });
+        }
+        if ingredient.share.is_zero() {
+            return Err(ContractError::ZeroShare {
+                ingredient: ingredient.ingredient_type.clone(),
+            });
+        }
+        if !catalogue
+            .iter()
+            .any(|info| info.id == ingredient.ingredient_type)
+        {
+            return Err(ContractError::UnknownIngredient {
+                ingredient: ingredient.ingredient_type.clone(),
+            });
+        }
+        total = total.checked_add(ingredient.share).map_err(StdError::from)?;
+    }
+
+    if total > share_precision {
+        return Err(ContractError::SharesExceedPrecision {
+            total,
+            max: share_precision,
+        });
+    }
+    Ok(())
 }
 
 pub fn check_weight(
@@ -20,31 +231,108 @@ pub fn check_weight(
     portions: &[IngredientPortion],
     weight: Uint128,
     share_precision: Uint128,
-) -> bool {
+) -> Result<(), ContractError> {
+    let mut needs = vec![];
+    add_ingredient_needs(&mut needs, ingredients, weight, share_precision)?;
+    check_stock(&needs, portions)
+}
+
+// adds the ingredients a recipe takes for `weight` to the needs of an order
+pub fn add_ingredient_needs(
+    needs: &mut Vec<IngredientPortion>,
+    ingredients: &[IngredientCupShare],
+    weight: Uint128,
+    share_precision: Uint128,
+) -> Result<(), ContractError> {
     for ingredient in ingredients.iter() {
-        for portion in portions.iter() {
-            if ingredient.ingredient_type != portion.ingredient {
-                continue;
-            }
-            if portion.weight
-                < calculate_total_ingredient_weight(weight, ingredient.share, share_precision)
-            {
-                return false;
-            }
+        let needed =
+            calculate_total_ingredient_weight(weight, ingredient.share, share_precision)?;
+        match needs
+            .iter_mut()
+            .find(|need| need.ingredient == ingredient.ingredient_type)
+        {
+            Some(need) => need.weight = need.weight.checked_add(needed)?,
+            None => needs.push(IngredientPortion {
+                ingredient: ingredient.ingredient_type.clone(),
+                weight: needed,
+            }),
+        }
+    }
+    Ok(())
+}
+
+// checks the combined needs of an order against the stock
+pub fn check_stock(
+    needs: &[IngredientPortion],
+    portions: &[IngredientPortion],
+) -> Result<(), ContractError> {
+    for need in needs.iter() {
+        let portion = portions
+            .iter()
+            .find(|portion| portion.ingredient == need.ingredient)
+            .ok_or_else(|| ContractError::UnknownIngredient {
+                ingredient: need.ingredient.clone(),
+            })?;
+        if portion.weight < need.weight {
+            return Err(ContractError::InsufficientIngredient {
+                ingredient: need.ingredient.clone(),
+                needed: need.weight,
+                available: portion.weight,
+            });
+        }
+    }
+    Ok(())
+}
+
+// how many cups of the given weight the stock allows and the ingredient that runs out first,
+// rounded like the purchase: n cups take floor(n * weight * share / precision) of an ingredient
+pub fn max_cups(
+    ingredients: &[IngredientCupShare],
+    portions: &[IngredientPortion],
+    weight: Uint128,
+    share_precision: Uint128,
+) -> (Uint128, Option<String>) {
+    let mut cups = Uint128::MAX;
+    let mut limiting = None;
+    for ingredient in ingredients.iter() {
+        let per_cup = weight.u128().saturating_mul(ingredient.share.u128());
+        if per_cup == 0 {
+            continue;
+        }
+        // the purchase refuses an ingredient missing from the stock, however little it takes
+        let allowed = match portions
+            .iter()
+            .find(|portion| portion.ingredient == ingredient.ingredient_type)
+        {
+            Some(portion) => Uint128::new(
+                portion
+                    .weight
+                    .u128()
+                    .saturating_add(1)
+                    .saturating_mul(share_precision.u128())
+                    .saturating_sub(1)
+                    / per_cup,
+            ),
+            None => Uint128::zero(),
+        };
+        if allowed < cups {
+            cups = allowed;
+            limiting = Some(ingredient.ingredient_type.clone());
         }
     }
-    true
+    (cups, limiting)
 }
 
 pub fn calculate_total_ingredient_weight(
     total_ingredients_weight: Uint128,
     ingredient_share: Uint128,
     share_precision: Uint128,
-) -> Uint128 {
+) -> Result<Uint128, ContractError> {
+    // cosmwasm-std 0.16 doesn't export DivideByZeroError, so it can't get a From impl
     total_ingredients_weight
-        .mul(ingredient_share)
+        .checked_mul(ingredient_share)?
         .checked_div(share_precision)
-        .unwrap()
+        .map_err(|_| ContractError::DivideByZero {})
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -54,66 +342,210 @@ pub struct CoffeeRecipe {
     pub ingredients: Vec<IngredientCupShare>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub enum Coffee {
-    Cappuccino,
-    Late,
-    Americano,
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct IngredientCupShare {
-    pub ingredient_type: Ingredient,
+    // id of an ingredient registered by the shop
+    pub ingredient_type: String,
     // percentages (100% is max)
     pub share: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct IngredientPortion {
-    pub ingredient: Ingredient,
+    pub ingredient: String,
+    // measured in the ingredient's unit
     pub weight: Uint128,
 }
 
+// an ingredient registered by a shop, e.g. { id: "oat_milk", name: "Oat milk", unit: Millilitres }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub enum Ingredient {
-    Sugar,
-    Milk,
-    Water,
-    Beans,
+pub struct IngredientInfo {
+    pub id: String,
+    pub name: String,
+    pub unit: Unit,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Grams,
+    Millilitres,
 }
 
 // We define a custom struct for each query response
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct OwnerResponse {
+    // none when the ownership was renounced
+    pub owner: Option<Addr>,
+    pub pending_owner: Option<Addr>,
+    pub pending_expires: Option<Expiration>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ConfigResponse {
+    pub owner: Option<Addr>,
+    pub coffee_token_addr: Addr,
+    // replaced payment tokens that can still be withdrawn
+    pub previous_token_addrs: Vec<Addr>,
+    pub order_timeout: u64,
+    // set while every shop is paused
+    pub pause: Option<PauseInfo>,
+    pub default_cup_weight: Uint128,
+    pub fee: Option<FeeConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LedgerResponse {
+    pub balances: Vec<Asset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct WithdrawalsResponse {
+    pub withdrawals: Vec<Withdrawal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ReconcileResponse {
+    pub token: Addr,
+    // what the token reports for the contract
+    pub balance: Uint128,
+    // owed to the shops
+    pub ledger: Uint128,
+    // held for open orders
+    pub escrowed: Uint128,
+    // the balance above or below the ledger and the escrow together
+    pub surplus: Uint128,
+    pub shortfall: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ShopInfoResponse {
+    pub shop_key: String,
     pub owner: Addr,
+    pub item_count: u64,
+    pub active: bool,
+    // set while the shop is paused
+    pub pause: Option<PauseInfo>,
+    // per cw20 token
+    pub revenue: Vec<Asset>,
+    pub native_revenue: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RolesResponse {
+    // granted roles only, the shop owner holds every role
+    pub staff: Vec<StaffRoles>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StaffRoles {
+    pub addr: Addr,
+    pub roles: Vec<Role>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ShopsResponse {
+    pub shops: Vec<ShopInfoResponse>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct MenuResponse {
-    pub menu: Vec<CoffeeCup>,
+    pub menu: Vec<MenuEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MenuEntry {
+    pub item: MenuItem,
+    // at least one size can be brewed from the current stock
+    pub available: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AvailabilityResponse {
+    pub items: Vec<ItemAvailability>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ItemAvailability {
+    pub id: Uint128,
+    pub name: String,
+    pub sizes: Vec<SizeAvailability>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SizeAvailability {
+    pub size: String,
+    pub cups: Uint128,
+    // none when the recipe has no ingredients
+    pub limiting_ingredient: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct RecipesResponse {
-    pub recipes: Vec<CoffeeRecipe>,
+    pub recipes: Vec<RecipeInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RecipeInfo {
+    pub id: Uint128,
+    pub recipe: CoffeeRecipe,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct IngredientsResponse {
     pub ingredients: Vec<IngredientPortion>,
+    pub catalogue: Vec<IngredientInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SimulatePurchaseResponse {
+    pub size: String,
+    // price of a cup with the modifiers
+    pub price: Uint128,
+    pub total: Uint128,
+    // set when the item can be paid in native coins
+    pub native_total: Option<Coin>,
+    // taken from the stock by the purchase
+    pub ingredients: Vec<IngredientPortion>,
+    pub enough_stock: bool,
+    pub short_ingredients: Vec<String>,
+    // set when buying would fail, to the error the purchase returns
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OrdersResponse {
+    pub orders: Vec<CoffeeOrder>,
 }
 
 #[cfg(test)]
 mod tests {
     use cosmwasm_std::Uint128;
 
+    use crate::error::ContractError;
     use crate::products::{
-        calculate_total_ingredient_weight, check_weight, Ingredient, IngredientCupShare,
-        IngredientPortion, SHARE_PRECISION,
+        apply_modifiers, calculate_total_ingredient_weight, check_weight, max_cups,
+        validate_modifiers, validate_recipe, CoffeeRecipe, IngredientCupShare, IngredientInfo,
+        IngredientPortion, Modifier, ModifierEffect, Unit, AVERAGE_CUP_WEIGHT, SHARE_PRECISION,
     };
 
     #[test]
@@ -122,44 +554,52 @@ mod tests {
         let share = Uint128::new(45);
         let total = calculate_total_ingredient_weight(weight, share, SHARE_PRECISION);
 
-        assert_eq!(total, share)
+        assert_eq!(total, Ok(share));
+        assert_eq!(
+            calculate_total_ingredient_weight(Uint128::MAX, share, SHARE_PRECISION),
+            Err(ContractError::Overflow {})
+        );
+        assert_eq!(
+            calculate_total_ingredient_weight(weight, share, Uint128::zero()),
+            Err(ContractError::DivideByZero {})
+        );
     }
 
     #[test]
     fn check_weight_test() {
         let ingredient_portions = vec![
             IngredientPortion {
-                ingredient: Ingredient::Beans,
+                ingredient: String::from("beans"),
                 weight: Uint128::new(100),
             },
             IngredientPortion {
-                ingredient: Ingredient::Water,
+                ingredient: String::from("water"),
                 weight: Uint128::new(100),
             },
             IngredientPortion {
-                ingredient: Ingredient::Milk,
+                ingredient: String::from("milk"),
                 weight: Uint128::new(100),
             },
             IngredientPortion {
-                ingredient: Ingredient::Sugar,
+                ingredient: String::from("sugar"),
                 weight: Uint128::new(100),
             },
         ];
         let ingredients = vec![
             IngredientCupShare {
-                ingredient_type: Ingredient::Water,
+                ingredient_type: String::from("water"),
                 share: Uint128::new(45),
             },
             IngredientCupShare {
-                ingredient_type: Ingredient::Beans,
+                ingredient_type: String::from("beans"),
                 share: Uint128::new(25),
             },
             IngredientCupShare {
-                ingredient_type: Ingredient::Milk,
+                ingredient_type: String::from("milk"),
                 share: Uint128::new(25),
             },
             IngredientCupShare {
-                ingredient_type: Ingredient::Sugar,
+                ingredient_type: String::from("sugar"),
                 share: Uint128::new(5),
             },
         ];
@@ -171,7 +611,7 @@ mod tests {
                 Uint128::new(100),
                 SHARE_PRECISION
             ),
-            true
+            Ok(())
         );
 
         assert_eq!(
@@ -181,7 +621,7 @@ mod tests {
                 Uint128::new(200),
                 SHARE_PRECISION
             ),
-            true
+            Ok(())
         );
 
         assert_eq!(
@@ -191,7 +631,249 @@ mod tests {
                 Uint128::new(1000),
                 SHARE_PRECISION
             ),
-            false
+            Err(ContractError::InsufficientIngredient {
+                ingredient: String::from("water"),
+                needed: Uint128::new(450),
+                available: Uint128::new(100),
+            })
+        );
+
+        // ingredients without a stock entry are rejected instead of skipped
+        let unknown = vec![IngredientCupShare {
+            ingredient_type: String::from("cocoa"),
+            share: Uint128::new(5),
+        }];
+        assert_eq!(
+            check_weight(
+                &unknown,
+                &ingredient_portions,
+                Uint128::new(100),
+                SHARE_PRECISION
+            ),
+            Err(ContractError::UnknownIngredient {
+                ingredient: String::from("cocoa")
+            })
+        );
+    }
+
+    #[test]
+    fn validate_recipe_test() {
+        let catalogue = vec![
+            IngredientInfo {
+                id: String::from("beans"),
+                name: String::from("Beans"),
+                unit: Unit::Grams,
+            },
+            IngredientInfo {
+                id: String::from("water"),
+                name: String::from("Water"),
+                unit: Unit::Millilitres,
+            },
+        ];
+        let share = |ingredient_type: &str, share: u128| IngredientCupShare {
+            ingredient_type: String::from(ingredient_type),
+            share: Uint128::new(share),
+        };
+        let recipe = |ingredients: Vec<IngredientCupShare>| CoffeeRecipe { ingredients };
+
+        let valid = recipe(vec![
+            share("water", 75),
+            share("beans", 25),
+        ]);
+        assert_eq!(validate_recipe(&valid, &catalogue, SHARE_PRECISION), Ok(()));
+
+        let duplicate = recipe(vec![
+            share("beans", 2),
+            share("water", 45),
+            share("beans", 25),
+        ]);
+        assert_eq!(
+            validate_recipe(&duplicate, &catalogue, SHARE_PRECISION),
+            Err(ContractError::DuplicateIngredient {
+                ingredient: String::from("beans")
+            })
+        );
+
+        let zero = recipe(vec![share("water", 0)]);
+        assert_eq!(
+            validate_recipe(&zero, &catalogue, SHARE_PRECISION),
+            Err(ContractError::ZeroShare {
+                ingredient: String::from("water")
+            })
+        );
+
+        let not_stocked = recipe(vec![share("milk", 30)]);
+        assert_eq!(
+            validate_recipe(&not_stocked, &catalogue, SHARE_PRECISION),
+            Err(ContractError::UnknownIngredient {
+                ingredient: String::from("milk")
+            })
+        );
+
+        let too_much = recipe(vec![
+            share("water", 80),
+            share("beans", 25),
+        ]);
+        assert_eq!(
+            validate_recipe(&too_much, &catalogue, SHARE_PRECISION),
+            Err(ContractError::SharesExceedPrecision {
+                total: Uint128::new(105),
+                max: SHARE_PRECISION
+            })
+        );
+    }
+
+    #[test]
+    fn apply_modifiers_test() {
+        let share = |ingredient_type: &str, share: u128| IngredientCupShare {
+            ingredient_type: String::from(ingredient_type),
+            share: Uint128::new(share),
+        };
+        let modifier = |id: &str, effects: Vec<ModifierEffect>| Modifier {
+            id: String::from(id),
+            name: String::from(id),
+            price_delta: Uint128::zero(),
+            native_price_delta: Uint128::zero(),
+            effects,
+        };
+        let latte = CoffeeRecipe {
+            ingredients: vec![
+                share("water", 25),
+                share("beans", 25),
+                share("milk", 45),
+                share("sugar", 5),
+            ],
+        };
+
+        let extra_shot = modifier(
+            "extra_shot",
+            vec![ModifierEffect::Add {
+                ingredient: String::from("beans"),
+                share: Uint128::new(10),
+            }],
+        );
+        let no_sugar = modifier(
+            "no_sugar",
+            vec![ModifierEffect::Remove {
+                ingredient: String::from("sugar"),
+            }],
+        );
+        let oat_milk = modifier(
+            "oat_milk",
+            vec![ModifierEffect::Substitute {
+                from: String::from("milk"),
+                to: String::from("oat_milk"),
+            }],
+        );
+
+        assert_eq!(
+            apply_modifiers(&latte, &[&extra_shot, &no_sugar, &oat_milk]),
+            CoffeeRecipe {
+                ingredients: vec![
+                    share("water", 25),
+                    share("beans", 35),
+                    share("oat_milk", 45),
+                ],
+            }
+        );
+        assert_eq!(apply_modifiers(&latte, &[]), latte);
+
+        let catalogue = vec![
+            IngredientInfo {
+                id: String::from("milk"),
+                name: String::from("Milk"),
+                unit: Unit::Millilitres,
+            },
+            IngredientInfo {
+                id: String::from("beans"),
+                name: String::from("Beans"),
+                unit: Unit::Grams,
+            },
+        ];
+        assert_eq!(
+            validate_modifiers(&[extra_shot.clone(), extra_shot.clone()], &catalogue),
+            Err(ContractError::InvalidParam {})
+        );
+        assert_eq!(validate_modifiers(&[extra_shot], &catalogue), Ok(()));
+        assert_eq!(
+            validate_modifiers(&[oat_milk], &catalogue),
+            Err(ContractError::UnknownIngredient {
+                ingredient: String::from("oat_milk")
+            })
+        );
+    }
+
+    #[test]
+    fn max_cups_test() {
+        let portion = |ingredient: &str, weight: u128| IngredientPortion {
+            ingredient: String::from(ingredient),
+            weight: Uint128::new(weight),
+        };
+        let share = |ingredient: &str, share: u128| IngredientCupShare {
+            ingredient_type: String::from(ingredient),
+            share: Uint128::new(share),
+        };
+        let cappuccino = vec![
+            share("water", 45),
+            share("beans", 25),
+            share("milk", 25),
+            share("sugar", 5),
+        ];
+        let weight = Uint128::new(AVERAGE_CUP_WEIGHT);
+        let portions = vec![
+            portion("water", 1000),
+            portion("beans", 200),
+            portion("milk", 500),
+            portion("sugar", 100),
+        ];
+
+        // a cup takes 62 of the beans, so 3 cups take 187 and 4 would take 250
+        assert_eq!(
+            max_cups(&cappuccino, &portions, weight, SHARE_PRECISION),
+            (Uint128::new(3), Some(String::from("beans")))
+        );
+        assert_eq!(
+            check_weight(&cappuccino, &portions, weight * Uint128::new(3), SHARE_PRECISION),
+            Ok(())
+        );
+        assert_eq!(
+            check_weight(&cappuccino, &portions, weight * Uint128::new(4), SHARE_PRECISION),
+            Err(ContractError::InsufficientIngredient {
+                ingredient: String::from("beans"),
+                needed: Uint128::new(250),
+                available: Uint128::new(200),
+            })
+        );
+
+        // an ingredient missing from the stock can't be brewed at all
+        assert_eq!(
+            max_cups(&cappuccino, &portions[..3], weight, SHARE_PRECISION),
+            (Uint128::zero(), Some(String::from("sugar")))
+        );
+        // even when a cup takes less than a unit of it
+        assert_eq!(
+            max_cups(
+                &cappuccino,
+                &portions[..3],
+                Uint128::new(1),
+                SHARE_PRECISION
+            ),
+            (Uint128::zero(), Some(String::from("sugar")))
+        );
+        assert_eq!(
+            check_weight(
+                &cappuccino,
+                &portions[..3],
+                Uint128::new(1),
+                SHARE_PRECISION
+            ),
+            Err(ContractError::UnknownIngredient {
+                ingredient: String::from("sugar"),
+            })
+        );
+        assert_eq!(
+            max_cups(&[], &portions, weight, SHARE_PRECISION),
+            (Uint128::MAX, None)
         );
     }
 }
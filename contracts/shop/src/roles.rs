@@ -0,0 +1,72 @@
+use cosmwasm_std::{Addr, StdResult, Storage};
+use cw_storage_plus::Map;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::coffee_state::CoffeeState;
+use crate::error::ContractError;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    // everything below plus granting and revoking roles
+    Owner,
+    // prices, menu, ingredient catalogue and refunds
+    Manager,
+    // ingredient stock
+    InventoryClerk,
+    // order status
+    Barista,
+    // withdrawals
+    Treasurer,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Manager => "manager",
+            Role::InventoryClerk => "inventory_clerk",
+            Role::Barista => "barista",
+            Role::Treasurer => "treasurer",
+        }
+    }
+}
+
+// roles granted to the staff of each shop, the shop owner holds every role without a grant
+pub const SHOP_ROLES: Map<(String, Addr), Vec<Role>> = Map::new("shop_roles");
+
+pub fn roles_of(storage: &dyn Storage, shop_key: &str, addr: &Addr) -> StdResult<Vec<Role>> {
+    Ok(SHOP_ROLES
+        .may_load(storage, (shop_key.to_string(), addr.clone()))?
+        .unwrap_or_default())
+}
+
+// owners can do whatever any other role can
+pub fn has_role(
+    storage: &dyn Storage,
+    shop_key: &str,
+    coffee_state: &CoffeeState,
+    addr: &Addr,
+    role: Role,
+) -> StdResult<bool> {
+    if coffee_state.owner == *addr {
+        return Ok(true);
+    }
+    let roles = roles_of(storage, shop_key, addr)?;
+    Ok(roles.contains(&Role::Owner) || roles.contains(&role))
+}
+
+pub fn require_role(
+    storage: &dyn Storage,
+    shop_key: &str,
+    coffee_state: &CoffeeState,
+    addr: &Addr,
+    role: Role,
+) -> Result<(), ContractError> {
+    if has_role(storage, shop_key, coffee_state, addr, role)? {
+        Ok(())
+    } else {
+        Err(ContractError::Unauthorized {})
+    }
+}
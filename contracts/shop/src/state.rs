@@ -1,13 +1,91 @@
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, BlockInfo, Decimal, Timestamp, Uint128};
+use cw20::Expiration;
 use cw_storage_plus::Item;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::products::AVERAGE_CUP_WEIGHT;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
-    pub owner: Addr,
-    pub balance: Uint128,
+    // none once the ownership is renounced
+    pub owner: Option<Addr>,
+    // the token new orders are paid with
     pub coffee_token_addr: Addr,
+    // tokens that were accepted before, balances left in them can still be withdrawn
+    #[serde(default)]
+    pub previous_token_addrs: Vec<Addr>,
+    // seconds after which the customer can reclaim the payment of an order that wasn't picked up
+    #[serde(default = "default_order_timeout")]
+    pub order_timeout: u64,
+    // stops the sales of every shop
+    #[serde(default)]
+    pub pause: Option<PauseInfo>,
+    // weight of the cup sizes that are added without one
+    #[serde(default = "default_cup_weight")]
+    pub default_cup_weight: Uint128,
+    // cut of every withdrawal that goes to the fee collector
+    #[serde(default)]
+    pub fee: Option<FeeConfig>,
+}
+
+pub const DEFAULT_ORDER_TIMEOUT: u64 = 60 * 60;
+
+fn default_order_timeout() -> u64 {
+    DEFAULT_ORDER_TIMEOUT
+}
+
+fn default_cup_weight() -> Uint128 {
+    Uint128::new(AVERAGE_CUP_WEIGHT)
 }
 
 pub const STATE: Item<State> = Item::new("state");
+
+impl State {
+    pub fn is_owner(&self, addr: &Addr) -> bool {
+        self.owner.as_ref() == Some(addr)
+    }
+
+    // the current payment token or one it replaced
+    pub fn is_payment_token(&self, addr: &Addr) -> bool {
+        self.coffee_token_addr == *addr || self.previous_token_addrs.contains(addr)
+    }
+
+    // the fee and the rest of a withdrawn amount
+    pub fn split_fee(&self, amount: Uint128) -> (Uint128, Uint128) {
+        let fee = self.fee.as_ref().map_or(Uint128::zero(), |fee| amount * fee.rate);
+        (fee, amount - fee)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeConfig {
+    pub collector: Addr,
+    // e.g. 0.02 for 2%, below 1 so the shop keeps part of every sale
+    pub rate: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseInfo {
+    pub reason: Option<String>,
+    // the pause lifts by itself at this block time
+    pub expires: Option<Timestamp>,
+}
+
+impl PauseInfo {
+    pub fn is_active(&self, block: &BlockInfo) -> bool {
+        match self.expires {
+            Some(expires) => block.time < expires,
+            None => true,
+        }
+    }
+}
+
+// the ownership moves once the proposed owner accepts it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnershipProposal {
+    pub owner: Addr,
+    pub expires: Expiration,
+}
+
+pub const OWNERSHIP_PROPOSAL: Item<OwnershipProposal> = Item::new("ownership_proposal");
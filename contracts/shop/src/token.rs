@@ -1,5 +1,5 @@
 use cosmwasm_std::{Addr, Uint128, QuerierWrapper, QueryRequest, WasmQuery, CosmosMsg, WasmMsg, StdResult, to_binary, Response};
-use cw20::{Cw20QueryMsg, Cw20ExecuteMsg, BalanceResponse};
+use cw20::{Cw20QueryMsg, Cw20ExecuteMsg, BalanceResponse, AllowanceResponse};
 use crate::error::ContractError;
 
 pub fn query_token_balance(
@@ -7,21 +7,34 @@ pub fn query_token_balance(
     contract_addr: Addr,
     account_addr: Addr,
 ) -> StdResult<Uint128> {
-    // load balance form the token contract
-    let res: BalanceResponse = querier
-        .query(&QueryRequest::Wasm(WasmQuery::Smart {
-            contract_addr: String::from(contract_addr),
-            msg: to_binary(&Cw20QueryMsg::Balance {
-                address: String::from(account_addr),
-            })?,
-        }))
-        .unwrap_or_else(|_| BalanceResponse {
-            balance: Uint128::zero(),
-        });
+    // load balance form the token contract, a failing query isn't a zero balance
+    let res: BalanceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: String::from(contract_addr),
+        msg: to_binary(&Cw20QueryMsg::Balance {
+            address: String::from(account_addr),
+        })?,
+    }))?;
 
     Ok(res.balance)
 }
 
+pub fn query_token_allowance(
+    querier: &QuerierWrapper,
+    contract_addr: Addr,
+    owner: Addr,
+    spender: Addr,
+) -> StdResult<Uint128> {
+    let res: AllowanceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: String::from(contract_addr),
+        msg: to_binary(&Cw20QueryMsg::Allowance {
+            owner: String::from(owner),
+            spender: String::from(spender),
+        })?,
+    }))?;
+
+    Ok(res.allowance)
+}
+
 pub fn execute_transfer(contract_addr: Addr, recipient: Addr, amount: Uint128) -> Result<Response, ContractError> {
     let res = Response::new()
         .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
@@ -0,0 +1,40 @@
+use cosmwasm_std::{Addr, StdResult, Storage, Timestamp, Uint128};
+use cw_storage_plus::{Item, Map, U128Key};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::asset::Asset;
+
+// a payout from the ledger of a shop
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Withdrawal {
+    pub id: Uint128,
+    pub shop_key: String,
+    // taken from the ledger, the fee included
+    pub asset: Asset,
+    // the cut that went to the fee collector
+    pub fee: Uint128,
+    pub recipient: Addr,
+    pub withdrawn_by: Addr,
+    pub time: Timestamp,
+}
+
+// the withdrawals of every shop, oldest first
+pub const WITHDRAWALS: Map<(String, U128Key), Withdrawal> = Map::new("withdrawals");
+pub const LAST_WITHDRAWAL_ID: Item<Uint128> = Item::new("last_withdrawal_id");
+
+pub fn next_withdrawal_id(storage: &mut dyn Storage) -> StdResult<Uint128> {
+    let id = LAST_WITHDRAWAL_ID.may_load(storage)?.unwrap_or_default() + Uint128::new(1);
+    LAST_WITHDRAWAL_ID.save(storage, &id)?;
+    Ok(id)
+}
+
+impl Withdrawal {
+    pub fn save(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        WITHDRAWALS.save(
+            storage,
+            (self.shop_key.clone(), U128Key::new(self.id.u128())),
+            self,
+        )
+    }
+}